@@ -0,0 +1,381 @@
+use crate::git_controller::{
+    ChangeKind, ChangedFile, GitBackend, GitController, GitEvent, CANCELLED_MESSAGE, POLL_INTERVAL,
+};
+use crate::setting_util::PullStrategy;
+use gix::progress::Discard;
+use gix::remote::fetch::refs::update::Mode;
+use gix::remote::fetch::Status;
+use gix::remote::Direction;
+use gix::status::index_worktree::iter::Summary;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Instant;
+
+// backend: builtin. Clone, fetch, status and config run in-process with gix, so they need
+// no git binary and no output parsing; everything else goes to the wrapped GitController,
+// which also provides the directory, logging, retries, timeout and cancellation.
+pub struct BuiltinGit {
+    process: GitController,
+}
+
+impl BuiltinGit {
+    pub fn new(process: GitController) -> Self {
+        BuiltinGit { process }
+    }
+
+    // gix stops once `interrupt` is raised, which happens when the run is cancelled or
+    // the timeout passes. Errors are reported like git's own "fatal: ..." lines.
+    fn run_interruptible(
+        &self,
+        args: &[&str],
+        op: impl FnOnce(&AtomicBool) -> Result<String, String>,
+    ) -> (Option<i32>, String) {
+        if self.process.is_cancelled() {
+            return (None, CANCELLED_MESSAGE.to_string());
+        }
+        let cancel_flag = self.process.cancel_flag();
+        let timeout = self.process.timeout();
+        let interrupt = AtomicBool::new(false);
+        let timed_out = AtomicBool::new(false);
+        let done = AtomicBool::new(false);
+        let started_at = Instant::now();
+
+        let result = thread::scope(|scope| {
+            scope.spawn(|| {
+                while !done.load(Ordering::SeqCst) {
+                    let cancelled = cancel_flag
+                        .as_ref()
+                        .is_some_and(|flag| flag.load(Ordering::SeqCst));
+                    let expired = timeout.is_some_and(|t| started_at.elapsed() >= t);
+                    if cancelled || expired {
+                        timed_out.store(expired, Ordering::SeqCst);
+                        interrupt.store(true, Ordering::SeqCst);
+                        return;
+                    }
+                    thread::sleep(POLL_INTERVAL);
+                }
+            });
+            let result = op(&interrupt);
+            done.store(true, Ordering::SeqCst);
+            result
+        });
+
+        let (exit_code, output) = match result {
+            Ok(output) => (Some(0), output),
+            Err(_) if timed_out.load(Ordering::SeqCst) => (
+                None,
+                format!(
+                    "fatal: timed out after {}s\n",
+                    timeout.unwrap_or_default().as_secs()
+                ),
+            ),
+            Err(_) if interrupt.load(Ordering::SeqCst) => (None, CANCELLED_MESSAGE.to_string()),
+            Err(e) => (Some(128), format!("fatal: {e}\n")),
+        };
+        self.process.record_builtin(args, exit_code, &output);
+        (exit_code, output)
+    }
+
+    fn changed_files(&self) -> Result<Vec<ChangedFile>, String> {
+        let repo = gix::open(self.repo_dir()).map_err(describe)?;
+        let items = repo
+            .status(Discard)
+            .map_err(describe)?
+            .untracked_files(gix::status::UntrackedFiles::Files)
+            .index_worktree_rewrites(None)
+            .into_iter(Vec::new())
+            .map_err(describe)?;
+
+        // Sorted by path like `git status`; a file can be both staged and modified
+        let mut files: BTreeMap<String, ChangedFile> = BTreeMap::new();
+        for item in items {
+            match item.map_err(describe)? {
+                gix::status::Item::TreeIndex(change) => {
+                    let file = file_entry(&mut files, &change.location().to_string());
+                    file.kind = ChangeKind::Staged;
+                    if let gix::diff::index::ChangeRef::Rewrite {
+                        source_location, ..
+                    } = &change
+                    {
+                        file.orig_path = Some(source_location.to_string());
+                    }
+                }
+                gix::status::Item::IndexWorktree(item) => {
+                    let Some(summary) = item.summary() else {
+                        continue;
+                    };
+                    let file = file_entry(&mut files, &item.rela_path().to_string());
+                    if summary == Summary::Added {
+                        file.kind = ChangeKind::Untracked;
+                    }
+                    file.needs_add = true;
+                }
+            }
+        }
+        Ok(files.into_values().collect())
+    }
+
+    fn repo_dir(&self) -> &Path {
+        self.process.dir().unwrap_or(Path::new("."))
+    }
+}
+
+fn file_entry<'a>(files: &'a mut BTreeMap<String, ChangedFile>, path: &str) -> &'a mut ChangedFile {
+    files
+        .entry(path.to_string())
+        .or_insert_with(|| ChangedFile {
+            path: path.to_string(),
+            orig_path: None,
+            kind: ChangeKind::Modified,
+            needs_add: false,
+            selected: true,
+        })
+}
+
+// One line per file, e.g. "staged    src/main.rs"
+fn describe_changes(files: &[ChangedFile]) -> String {
+    files
+        .iter()
+        .map(|file| {
+            let kind = match file.kind {
+                ChangeKind::Staged => "staged",
+                ChangeKind::Modified => "modified",
+                ChangeKind::Untracked => "untracked",
+            };
+            format!("{kind:9} {}\n", file.path)
+        })
+        .collect()
+}
+
+// The error and its causes on one line, so retries can classify network failures
+fn describe(error: impl Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+    message
+}
+
+// Like git, creates the leading directories of `dest`
+fn clone(
+    remote: &str,
+    branch: &str,
+    dest: &Path,
+    interrupt: &AtomicBool,
+) -> Result<String, String> {
+    if let Some(parent) = dest.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(describe)?;
+    }
+    let mut prepare = gix::prepare_clone(remote, dest)
+        .map_err(describe)?
+        .with_ref_name(Some(branch))
+        .map_err(describe)?;
+    let (mut checkout, _) = prepare
+        .fetch_then_checkout(Discard, interrupt)
+        .map_err(describe)?;
+    checkout
+        .main_worktree(Discard, interrupt)
+        .map_err(describe)?;
+    Ok(format!("Cloned {remote} into {}\n", dest.display()))
+}
+
+// Like `git config key value`: sets the key in the repo's own config file, written to
+// config.lock first and then moved into place as git does
+fn set_config(repo_dir: &Path, key: &str, value: &str) -> Result<String, String> {
+    let repo = gix::open(repo_dir).map_err(describe)?;
+    let path = repo.common_dir().join("config");
+    let mut config =
+        gix::config::File::from_path_no_includes(path.clone(), gix::config::Source::Local)
+            .map_err(describe)?;
+    let parsed = gix::config::KeyRef::parse_unvalidated(key.into())
+        .ok_or_else(|| format!("key does not contain a section: {key}"))?;
+    config
+        .set_raw_value_by(
+            parsed.section_name,
+            parsed.subsection_name,
+            parsed.value_name.to_string(),
+            value,
+        )
+        .map_err(describe)?;
+
+    let mut out = Vec::new();
+    config.write_to(&mut out).map_err(describe)?;
+    let lock = path.with_extension("lock");
+    fs::write(&lock, out).map_err(describe)?;
+    fs::rename(&lock, &path).map_err(describe)?;
+    Ok(String::new())
+}
+
+// Lists the remote-tracking refs that moved; rejected updates fail the fetch like in git
+fn fetch(repo_dir: &Path, interrupt: &AtomicBool) -> Result<String, String> {
+    let repo = gix::open(repo_dir).map_err(describe)?;
+    let remote = repo
+        .find_default_remote(Direction::Fetch)
+        .ok_or("no remote to fetch from")?
+        .map_err(describe)?;
+    let outcome = remote
+        .connect(Direction::Fetch)
+        .map_err(describe)?
+        .prepare_fetch(Discard, Default::default())
+        .map_err(describe)?
+        .receive(Discard, interrupt)
+        .map_err(describe)?;
+
+    let update_refs = match &outcome.status {
+        Status::NoPackReceived { update_refs, .. } | Status::Change { update_refs, .. } => {
+            update_refs
+        }
+    };
+    let mut output = String::new();
+    let mut rejected = false;
+    for update in &update_refs.updates {
+        if update.mode == Mode::NoChangeNeeded {
+            continue;
+        }
+        let name = update
+            .edit_index
+            .map(|i| update_refs.edits[i].name.as_bstr().to_string())
+            .unwrap_or_default();
+        rejected |= update.mode.to_string().starts_with("rejected");
+        output.push_str(&format!("{name}: {}\n", update.mode));
+    }
+
+    if rejected {
+        Err(format!("some local refs could not be updated\n{output}"))
+    } else {
+        Ok(output)
+    }
+}
+
+impl GitBackend for BuiltinGit {
+    fn is_cancelled(&self) -> bool {
+        self.process.is_cancelled()
+    }
+
+    fn git_clone(
+        &self,
+        remote: &str,
+        branch: &str,
+        on_event: &mut dyn FnMut(&GitEvent),
+    ) -> (Option<i32>, String) {
+        let dest = self.repo_dir().to_path_buf();
+        let dest_arg = dest.display().to_string();
+        let args = ["clone", remote, "-b", branch, dest_arg.as_str()];
+        if self.process.is_dry_run() {
+            self.process.record_builtin_dry_run(&args);
+            return (Some(0), String::new());
+        }
+        self.process.retry_transient(on_event, &mut |_| {
+            self.run_interruptible(&args, |interrupt| clone(remote, branch, &dest, interrupt))
+        })
+    }
+
+    fn git_fetch(&self, on_event: &mut dyn FnMut(&GitEvent)) -> (Option<i32>, String) {
+        if self.process.is_dry_run() {
+            self.process.record_builtin_dry_run(&["fetch"]);
+            return (Some(0), String::new());
+        }
+        self.process.retry_transient(on_event, &mut |_| {
+            self.run_interruptible(&["fetch"], |interrupt| fetch(self.repo_dir(), interrupt))
+        })
+    }
+
+    fn git_changed_files(&self) -> Vec<ChangedFile> {
+        // A failed status is logged and treated as "nothing to commit", like the process backend
+        match self.changed_files() {
+            Ok(files) => {
+                self.process
+                    .record_builtin(&["status"], Some(0), &describe_changes(&files));
+                files
+            }
+            Err(e) => {
+                self.process
+                    .record_builtin(&["status"], Some(128), &format!("fatal: {e}\n"));
+                Vec::new()
+            }
+        }
+    }
+
+    fn git_pull(
+        &self,
+        strategy: PullStrategy,
+        on_event: &mut dyn FnMut(&GitEvent),
+    ) -> (Option<i32>, String) {
+        self.process.git_pull(strategy, on_event)
+    }
+
+    fn git_conflicted_files(&self) -> Vec<String> {
+        self.process.git_conflicted_files()
+    }
+
+    fn git_pull_in_progress(&self) -> bool {
+        self.process.git_pull_in_progress()
+    }
+
+    fn git_abort_pull(&self) -> (Option<i32>, String) {
+        self.process.git_abort_pull()
+    }
+
+    fn git_stage(&self, files: &[&ChangedFile]) {
+        self.process.git_stage(files)
+    }
+
+    fn git_unstage(&self, files: &[&ChangedFile]) {
+        self.process.git_unstage(files)
+    }
+
+    fn git_staged_diff(&self, files: &[&ChangedFile]) -> String {
+        self.process.git_staged_diff(files)
+    }
+
+    fn git_commit(&self, commit_message: &str, files: &[&ChangedFile]) -> (Option<i32>, String) {
+        self.process.git_commit(commit_message, files)
+    }
+
+    fn git_current_branch(&self) -> Option<String> {
+        self.process.git_current_branch()
+    }
+
+    fn git_head(&self) -> Option<String> {
+        self.process.git_head()
+    }
+
+    fn git_reset_keep(&self, commit: &str) -> String {
+        self.process.git_reset_keep(commit)
+    }
+
+    fn git_remote_branches_containing(&self, commit: &str) -> Vec<String> {
+        self.process.git_remote_branches_containing(commit)
+    }
+
+    fn git_push(&self, on_event: &mut dyn FnMut(&GitEvent)) -> (Option<i32>, String) {
+        self.process.git_push(on_event)
+    }
+
+    fn git_config(&self, name: &str, email: &str) -> (Option<i32>, String) {
+        let result = self.git_config_raw("user.name", name);
+        if result.0 != Some(0) {
+            return result;
+        }
+        self.git_config_raw("user.email", email)
+    }
+
+    fn git_config_raw(&self, key: &str, value: &str) -> (Option<i32>, String) {
+        let args = ["config", key, value];
+        if self.process.is_dry_run() {
+            self.process.record_builtin_dry_run(&args);
+            return (Some(0), String::new());
+        }
+        self.run_interruptible(&args, |_| set_config(self.repo_dir(), key, value))
+    }
+
+    fn run_program(&self, program: &str, args: &[&str]) -> (Option<i32>, String) {
+        self.process.run_program(program, args)
+    }
+}
@@ -0,0 +1,162 @@
+use crate::git_controller::{ChangedFile, GitBackend, GitEvent};
+use crate::setting_util::PullStrategy;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+// A scripted GitBackend for tests: every call is recorded and returns what the
+// test set up beforehand, so worker logic can be checked without a git binary
+#[derive(Default)]
+pub struct FakeBackend {
+    pub clone_output: (Option<i32>, String),
+    pub fetch_output: (Option<i32>, String),
+    pub pull_output: (Option<i32>, String),
+    pub push_output: (Option<i32>, String),
+    pub commit_output: (Option<i32>, String),
+    pub config_output: (Option<i32>, String),
+    pub branch: Option<String>,
+    pub conflicted_files: Vec<String>,
+    pub pull_in_progress: bool, // Until "abort" is called
+    pub abort_output: (Option<i32>, String),
+    pub conflicted_after_abort: Vec<String>,
+    pub changed_files: Vec<ChangedFile>,
+    pub staged_diff: String,
+    pub program_exit_code: Option<i32>,
+    pub program_output: String,
+    pub cancelled: AtomicBool,
+    pub calls: Mutex<Vec<String>>,
+}
+
+impl FakeBackend {
+    pub fn new() -> Self {
+        FakeBackend {
+            clone_output: (Some(0), String::new()),
+            fetch_output: (Some(0), String::new()),
+            pull_output: (Some(0), String::new()),
+            push_output: (Some(0), String::new()),
+            commit_output: (Some(0), String::new()),
+            config_output: (Some(0), String::new()),
+            abort_output: (Some(0), String::new()),
+            branch: Some("main".to_string()),
+            ..Default::default()
+        }
+    }
+
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    pub fn called(&self, name: &str) -> bool {
+        self.calls().iter().any(|call| call.starts_with(name))
+    }
+
+    fn record(&self, call: String) {
+        self.calls.lock().unwrap().push(call);
+    }
+}
+
+impl GitBackend for FakeBackend {
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    fn git_clone(
+        &self,
+        remote: &str,
+        branch: &str,
+        _on_event: &mut dyn FnMut(&GitEvent),
+    ) -> (Option<i32>, String) {
+        self.record(format!("clone {remote} {branch}"));
+        self.clone_output.clone()
+    }
+
+    fn git_fetch(&self, _on_event: &mut dyn FnMut(&GitEvent)) -> (Option<i32>, String) {
+        self.record("fetch".to_string());
+        self.fetch_output.clone()
+    }
+
+    fn git_pull(
+        &self,
+        strategy: PullStrategy,
+        _on_event: &mut dyn FnMut(&GitEvent),
+    ) -> (Option<i32>, String) {
+        self.record(format!("pull {}", strategy.as_str()));
+        self.pull_output.clone()
+    }
+
+    fn git_conflicted_files(&self) -> Vec<String> {
+        if self.called("abort") {
+            self.conflicted_after_abort.clone()
+        } else {
+            self.conflicted_files.clone()
+        }
+    }
+
+    fn git_pull_in_progress(&self) -> bool {
+        self.pull_in_progress && !self.called("abort")
+    }
+
+    fn git_abort_pull(&self) -> (Option<i32>, String) {
+        self.record("abort".to_string());
+        self.abort_output.clone()
+    }
+
+    fn git_changed_files(&self) -> Vec<ChangedFile> {
+        self.changed_files.clone()
+    }
+
+    fn git_stage(&self, files: &[&ChangedFile]) {
+        let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        self.record(format!("stage {}", paths.join(" ")));
+    }
+
+    fn git_unstage(&self, files: &[&ChangedFile]) {
+        let paths: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        self.record(format!("unstage {}", paths.join(" ")));
+    }
+
+    fn git_staged_diff(&self, _files: &[&ChangedFile]) -> String {
+        self.staged_diff.clone()
+    }
+
+    fn git_commit(&self, commit_message: &str, _files: &[&ChangedFile]) -> (Option<i32>, String) {
+        self.record(format!("commit {commit_message}"));
+        self.commit_output.clone()
+    }
+
+    fn git_current_branch(&self) -> Option<String> {
+        self.branch.clone()
+    }
+
+    fn git_head(&self) -> Option<String> {
+        None
+    }
+
+    fn git_reset_keep(&self, commit: &str) -> String {
+        self.record(format!("reset {commit}"));
+        String::new()
+    }
+
+    fn git_remote_branches_containing(&self, _commit: &str) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn git_push(&self, _on_event: &mut dyn FnMut(&GitEvent)) -> (Option<i32>, String) {
+        self.record("push".to_string());
+        self.push_output.clone()
+    }
+
+    fn git_config(&self, name: &str, email: &str) -> (Option<i32>, String) {
+        self.record(format!("config {name} {email}"));
+        self.config_output.clone()
+    }
+
+    fn git_config_raw(&self, key: &str, value: &str) -> (Option<i32>, String) {
+        self.record(format!("config {key} {value}"));
+        self.config_output.clone()
+    }
+
+    fn run_program(&self, program: &str, args: &[&str]) -> (Option<i32>, String) {
+        self.record(format!("run {program} {}", args.join(" ")));
+        (self.program_exit_code, self.program_output.clone())
+    }
+}
@@ -1,84 +1,1032 @@
-use std::process::Command;
+use crate::setting_util::PullStrategy;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-// trait GitControllerInterface {
-//     fn new() -> Self;
-//     fn git_status() -> String;
-//     fn git_clone(repo_name: &str) -> String;
-//     fn exec_git_command(sub_command: &str) -> String;
-//     fn exec_command(cmd: &str, args: Vec<&str>) -> String;
-// }
+// How often a running command is checked against its timeout and cancellation
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// How long a cancelled command gets to exit after SIGTERM before it is killed
+const CANCEL_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+pub const CANCELLED_MESSAGE: &str = "fatal: cancelled\n";
+
+// The read-only git subcommands the workers query with; --dry-run still runs these
+// and records every other command instead of running it
+const QUERY_COMMANDS: &[&str] = &[
+    "diff",
+    "for-each-ref",
+    "rev-parse",
+    "status",
+    "symbolic-ref",
+];
+
+// First retry waits this long, each further retry twice as long as the one before
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+
+// Everything the workers do to a repository. GitController runs the git binary,
+// BuiltinGit (feature builtin-git) does some of it in-process, tests use a scripted fake.
+pub trait GitBackend {
+    fn is_cancelled(&self) -> bool;
+    fn git_clone(
+        &self,
+        remote: &str,
+        branch: &str,
+        on_event: &mut dyn FnMut(&GitEvent),
+    ) -> (Option<i32>, String);
+    fn git_fetch(&self, on_event: &mut dyn FnMut(&GitEvent)) -> (Option<i32>, String);
+    fn git_pull(
+        &self,
+        strategy: PullStrategy,
+        on_event: &mut dyn FnMut(&GitEvent),
+    ) -> (Option<i32>, String);
+    fn git_conflicted_files(&self) -> Vec<String>;
+    fn git_pull_in_progress(&self) -> bool;
+    fn git_abort_pull(&self) -> (Option<i32>, String);
+    fn git_changed_files(&self) -> Vec<ChangedFile>;
+    fn git_stage(&self, files: &[&ChangedFile]);
+    fn git_unstage(&self, files: &[&ChangedFile]);
+    fn git_staged_diff(&self, files: &[&ChangedFile]) -> String;
+    fn git_commit(&self, commit_message: &str, files: &[&ChangedFile]) -> (Option<i32>, String);
+    fn git_current_branch(&self) -> Option<String>;
+    fn git_head(&self) -> Option<String>;
+    fn git_reset_keep(&self, commit: &str) -> String;
+    fn git_remote_branches_containing(&self, commit: &str) -> Vec<String>;
+    fn git_push(&self, on_event: &mut dyn FnMut(&GitEvent)) -> (Option<i32>, String);
+    fn git_config(&self, name: &str, email: &str) -> (Option<i32>, String);
+    fn git_config_raw(&self, key: &str, value: &str) -> (Option<i32>, String);
+    // Any program, in the repo directory with the same logging, timeout and cancellation
+    fn run_program(&self, program: &str, args: &[&str]) -> (Option<i32>, String);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Staged,
+    Modified,
+    Untracked,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChangedFile {
+    pub path: String,
+    pub orig_path: Option<String>, // Source path of a rename or copy
+    pub kind: ChangeKind,
+    pub needs_add: bool, // Has changes in the working tree that are not staged yet
+    pub selected: bool,
+}
+
+// Parse `git status --porcelain -z --untracked-files=all`
+pub fn parse_porcelain(output: &str) -> Vec<ChangedFile> {
+    let mut files = Vec::new();
+    let mut entries = output.split('\0').filter(|e| !e.is_empty());
+
+    while let Some(entry) = entries.next() {
+        if entry.len() < 4 {
+            continue;
+        }
+        let (code, path) = entry.split_at(3);
+        let mut chars = code.chars();
+        let x = chars.next().unwrap_or(' ');
+        let y = chars.next().unwrap_or(' ');
+
+        let orig_path = if x == 'R' || x == 'C' {
+            entries.next().map(String::from)
+        } else {
+            None
+        };
+
+        let kind = if x == '?' {
+            ChangeKind::Untracked
+        } else if x != ' ' {
+            ChangeKind::Staged
+        } else {
+            ChangeKind::Modified
+        };
+
+        files.push(ChangedFile {
+            path: path.to_string(),
+            orig_path,
+            kind,
+            needs_add: x == '?' || y != ' ',
+            selected: true,
+        });
+    }
+
+    files
+}
+
+// One progress line from `git clone/fetch/pull --progress`, e.g.
+// "Receiving objects:  45% (450/1000), 1.20 MiB | 500.00 KiB/s"
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitProgress {
+    pub phase: String,
+    pub percent: u16,
+    pub rate: Option<String>,
+}
+
+impl GitProgress {
+    pub fn parse(line: &str) -> Option<GitProgress> {
+        let (phase, rest) = line.trim().split_once(':')?;
+        if phase != "Receiving objects" && phase != "Resolving deltas" {
+            return None;
+        }
+        let percent = rest.trim_start().split('%').next()?.trim().parse().ok()?;
+        let rate = rest
+            .split_once('|')
+            .map(|(_, rate)| rate.trim().trim_end_matches(", done.").to_string());
+
+        Some(GitProgress {
+            phase: phase.to_string(),
+            percent,
+            rate,
+        })
+    }
+
+    // Overall completion of the transfer in 0.0-1.0. Receiving dominates the
+    // wall time, resolving deltas is the short tail after it.
+    pub fn fraction(&self) -> f32 {
+        let percent = self.percent.min(100) as f32 / 100.0;
+        match self.phase.as_str() {
+            "Receiving objects" => 0.8 * percent,
+            _ => 0.8 + 0.2 * percent,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match &self.rate {
+            Some(rate) => format!("{} {}% ({rate})", self.phase, self.percent),
+            None => format!("{} {}%", self.phase, self.percent),
+        }
+    }
+}
+
+// What clone, pull and push report while they run
+#[derive(Debug, Clone, PartialEq)]
+pub enum GitEvent {
+    Progress(GitProgress),
+    Retry { attempt: u32, max: u32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureKind {
+    Transient, // Network trouble that may go away on its own
+    Permanent,
+}
+
+const PERMANENT_FAILURES: &[&str] = &[
+    "Permission denied",
+    "Repository not found",
+    "does not appear to be a git repository",
+    "Authentication failed",
+    "Host key verification failed",
+    "rejected",
+];
+
+const TRANSIENT_FAILURES: &[&str] = &[
+    "Connection reset",
+    "Connection timed out",
+    "Connection refused",
+    "Connection closed",
+    "Operation timed out",
+    "early EOF",
+    "unexpected disconnect",
+    "The remote end hung up unexpectedly",
+    "Could not resolve host",
+    "Could not read from remote repository",
+    "RPC failed",
+    "gnutls_handshake() failed",
+    "SSL_ERROR_SYSCALL",
+];
+
+// Permanent causes win, since git adds "Could not read from remote repository"
+// after almost any ssh failure
+pub fn classify_failure(output: &str) -> FailureKind {
+    if PERMANENT_FAILURES.iter().any(|p| output.contains(p)) {
+        FailureKind::Permanent
+    } else if TRANSIENT_FAILURES.iter().any(|p| output.contains(p)) {
+        FailureKind::Transient
+    } else {
+        FailureKind::Permanent
+    }
+}
+
+// A finished command and everything it printed, kept for the TUI log pane
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandLog {
+    pub command: String,
+    pub exit_code: Option<i32>,
+    pub output: String,
+    #[serde(default)]
+    pub dir: Option<String>, // Working directory, None for the current one
+    #[serde(default)]
+    pub dry_run: bool, // Recorded by --dry-run without running
+}
+
+fn pathspecs<'a>(files: &[&'a ChangedFile]) -> Vec<&'a str> {
+    let mut paths = Vec::new();
+    for file in files {
+        paths.push(file.path.as_str());
+        if let Some(orig) = &file.orig_path {
+            paths.push(orig.as_str());
+        }
+    }
+    paths
+}
+
+type LogSink = Box<dyn Fn(CommandLog) + Send>;
+
+// One attempt of a network operation, given the caller's event handler
+type Attempt<'a> = dyn FnMut(&mut dyn FnMut(&GitEvent)) -> (Option<i32>, String) + 'a;
 
 pub struct GitController {
     // fields
     encoding: &'static encoding_rs::Encoding,
+    log_sink: Option<LogSink>,
+    retries: u32,
+    timeout: Option<Duration>,
+    cancel_flag: Option<Arc<AtomicBool>>,
+    dir: Option<PathBuf>,
+    dry_run: bool,
+    env: Vec<(String, String)>,
+}
+
+impl Default for GitController {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-// impl GitControllerInterface for GitController {
 impl GitController {
     // methods
     pub fn new() -> Self {
+        // Nothing is printed here: workers run while the TUI or the plain output owns stdout
         let mut encoding = encoding_rs::UTF_8;
-        if cfg!(target_os = "linux") {
-            println!("Running on Linux.");
-        } else if cfg!(target_os = "windows") {
+        if cfg!(target_os = "windows") {
             // Windowsの場合の処理
-            println!("Running on Windows.");
-            // ここにWindows用のコードを追加
             encoding = encoding_rs::SHIFT_JIS;
-        } else {
-            // 他のOSの場合の処理
-            println!("Running on an unknown OS.");
         }
 
-        GitController { encoding }
+        GitController {
+            encoding,
+            log_sink: None,
+            retries: 0,
+            timeout: None,
+            cancel_flag: None,
+            dir: None,
+            dry_run: false,
+            env: Vec::new(),
+        }
+    }
+
+    // Extra environment variables for every command, e.g. GITP_REPO_NAME for hooks
+    pub fn with_env(mut self, env: Vec<(String, String)>) -> Self {
+        self.env = env;
+        self
+    }
+
+    // Run commands in this directory instead of the current one
+    pub fn with_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    // Only record commands that would change something; queries still run so the
+    // recorded commands match what a real run would do
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    // Retry clone, pull and push this many times after a transient failure
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
     }
 
-    #[allow(dead_code)]
-    pub fn git_status(&self) -> String {
-        self.exec_git_command("status")
+    // Receive a CommandLog for every command this controller runs
+    pub fn with_log_sink(mut self, sink: impl Fn(CommandLog) + Send + 'static) -> Self {
+        self.log_sink = Some(Box::new(sink));
+        self
     }
 
-    pub fn git_clone(&self, repo_name: &str, branch: &str) -> String {
-        let sub_command: String = format!("clone {repo_name} -b {branch}");
-        self.exec_git_command(&sub_command)
+    // Kill any command that runs longer than this
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
     }
 
-    pub fn git_pull(&self) -> String {
-        self.exec_git_command("pull")
+    // Once the flag is set, running commands are terminated and new ones are not started
+    pub fn with_cancel_flag(mut self, cancel_flag: Arc<AtomicBool>) -> Self {
+        self.cancel_flag = Some(cancel_flag);
+        self
     }
 
-    pub fn git_push(&self, commit_message: &str) -> String {
-        self.exec_git_command("add -A");
-        let commit_cmd = format!("commit -m \"{commit_message}\"");
-        self.exec_git_command(&commit_cmd);
-        self.exec_git_command("push")
+    fn is_rebase_in_progress(&self) -> bool {
+        ["rebase-merge", "rebase-apply"].iter().any(|dir| {
+            let path = self.exec_git_args(&["rev-parse", "--git-path", dir]);
+            match &self.dir {
+                Some(repo_dir) => repo_dir.join(path.trim()).exists(),
+                None => Path::new(path.trim()).exists(),
+            }
+        })
     }
 
-    pub fn git_config(&self, name: &str, email: &str) {
-        self.exec_git_command(&format!("config user.name \"{name}\""));
-        self.exec_git_command(&format!("config user.email \"{email}\""));
+    fn exec_git_args(&self, args: &[&str]) -> String {
+        self.exec_command("git", args.to_vec())
     }
 
-    pub fn git_config_raw(&self, key: &str, value: &str) {
-        self.exec_git_command(&format!("config {key} \"{value}\""));
+    // Like exec_git_args, for commands whose exit code matters
+    fn run_git(&self, args: &[&str]) -> (Option<i32>, String) {
+        self.run_process("git", args, &mut |_| {})
     }
 
-    fn exec_git_command(&self, sub_command: &str) -> String {
-        let sub_commands = sub_command.split(' ').collect();
-        self.exec_command("git", sub_commands)
+    // Run a network command, retrying transient failures with exponential backoff
+    fn exec_git_with_retry(
+        &self,
+        args: &[&str],
+        on_event: &mut dyn FnMut(&GitEvent),
+    ) -> (Option<i32>, String) {
+        self.retry_transient(on_event, &mut |on_event| {
+            self.exec_git_with_progress(args, on_event)
+        })
+    }
+
+    // Repeats run until it succeeds, fails for a non-transient reason or runs out of retries
+    pub fn retry_transient(
+        &self,
+        on_event: &mut dyn FnMut(&GitEvent),
+        run: &mut Attempt,
+    ) -> (Option<i32>, String) {
+        let mut attempt = 0;
+        loop {
+            let (exit_code, result) = run(on_event);
+            if exit_code == Some(0)
+                || attempt >= self.retries
+                || classify_failure(&result) != FailureKind::Transient
+            {
+                return (exit_code, result);
+            }
+
+            attempt += 1;
+            on_event(&GitEvent::Retry {
+                attempt,
+                max: self.retries,
+            });
+            let delay = RETRY_BASE_DELAY * 2u32.pow(attempt - 1);
+            let started_at = Instant::now();
+            while started_at.elapsed() < delay {
+                if self.is_cancelled() {
+                    return (None, CANCELLED_MESSAGE.to_string());
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+
+    // Like exec_git_args, but reports git's progress lines as they arrive
+    fn exec_git_with_progress(
+        &self,
+        args: &[&str],
+        on_event: &mut dyn FnMut(&GitEvent),
+    ) -> (Option<i32>, String) {
+        self.run_process("git", args, on_event)
     }
 
     pub fn exec_command(&self, cmd: &str, args: Vec<&str>) -> String {
-        println!("{cmd}");
+        self.run_process(cmd, &args, &mut |_| {}).1
+    }
 
-        let output = Command::new(cmd).args(args).output().unwrap();
+    // Both pipes are read on their own threads so the child can be stopped once
+    // it outlives the timeout or the run is cancelled. Lines redrawn with '\r'
+    // are not kept in the output.
+    fn run_process(
+        &self,
+        cmd: &str,
+        args: &[&str],
+        on_event: &mut dyn FnMut(&GitEvent),
+    ) -> (Option<i32>, String) {
+        let is_query = cmd == "git" && args.first().is_some_and(|a| QUERY_COMMANDS.contains(a));
+        // Queries and the abort still run after a cancel, so a pull that was stopped
+        // part way can be checked and put back instead of left mid-merge/mid-rebase
+        let is_cleanup = is_query || (cmd == "git" && args.get(1) == Some(&"--abort"));
+        let is_cancelled = || !is_cleanup && self.is_cancelled();
+        if is_cancelled() {
+            return (None, CANCELLED_MESSAGE.to_string());
+        }
+        if self.dry_run && !is_query {
+            self.record_dry_run(cmd, args);
+            return (Some(0), String::new());
+        }
 
-        let (stdout_result, _, _) = self.encoding.decode(&output.stdout);
-        let (stderr_result, _, _) = self.encoding.decode(&output.stderr);
+        let mut command = Command::new(cmd);
+        if let Some(dir) = self.working_dir(cmd, args) {
+            command.current_dir(dir);
+        }
+        command
+            .args(args)
+            .envs(self.env.iter().map(|(key, value)| (key, value)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if cmd == "git" {
+            let ssh_command = if is_query {
+                None
+            } else {
+                self.batch_ssh_command(self.working_dir(cmd, args))
+            };
+            make_non_interactive(&mut command, ssh_command);
+        }
 
-        let mut result = stdout_result.to_string();
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                let result = format!("error: failed to run {cmd}: {e}");
+                self.record(cmd, args, None, &result);
+                return (None, result);
+            }
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        read_pipe(child.stdout.take().unwrap(), false, sender.clone());
+        read_pipe(child.stderr.take().unwrap(), true, sender);
+
+        let mut stdout_bytes = Vec::new();
+        let mut kept = Vec::new();
+        let (exit_code, end) = wait_for_child(
+            &mut child,
+            &receiver,
+            self.timeout,
+            &is_cancelled,
+            &mut |output| match output {
+                PipeOutput::Stdout(bytes) => stdout_bytes.extend_from_slice(&bytes),
+                PipeOutput::StderrLine { bytes, redrawn } => {
+                    let (text, _, _) = self.encoding.decode(&bytes);
+                    if let Some(progress) = GitProgress::parse(&text) {
+                        on_event(&GitEvent::Progress(progress));
+                    }
+                    if !redrawn {
+                        kept.extend_from_slice(&bytes);
+                        kept.push(b'\n');
+                    }
+                }
+            },
+        );
+
+        let (stdout_result, _, _) = self.encoding.decode(&stdout_bytes);
+        let (stderr_result, _, _) = self.encoding.decode(&kept);
+
+        let mut result = String::new();
+        match end {
+            ChildEnd::TimedOut(timeout) => {
+                result.push_str(&format!("fatal: timed out after {}s\n", timeout.as_secs()))
+            }
+            ChildEnd::Cancelled => result.push_str(CANCELLED_MESSAGE),
+            ChildEnd::Exited => {}
+        }
+        result.push_str(stdout_result.as_ref());
         result.push_str(stderr_result.as_ref());
 
-        result
+        self.record(cmd, args, exit_code, &result);
+        (exit_code, result)
+    }
+
+    // The GIT_SSH_COMMAND for a git command that may connect: the ssh git would pick,
+    // from GIT_SSH_COMMAND or else core.sshCommand, in batch mode. None leaves GIT_SSH alone.
+    fn batch_ssh_command(&self, dir: Option<&Path>) -> Option<String> {
+        let ssh = self
+            .env_var("GIT_SSH_COMMAND")
+            .or_else(|| configured_ssh_command(dir, &self.env));
+        match ssh {
+            Some(ssh) => Some(format!("{ssh} -o BatchMode=yes")),
+            None if self.env_var("GIT_SSH").is_none() => Some("ssh -o BatchMode=yes".to_string()),
+            None => None,
+        }
+    }
+
+    // From with_env, or else gitp's own environment
+    fn env_var(&self, key: &str) -> Option<String> {
+        self.env
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.clone())
+            .or_else(|| env::var(key).ok())
+    }
+
+    // A clone runs where gitp was started and creates the controller's directory
+    fn working_dir(&self, cmd: &str, args: &[&str]) -> Option<&Path> {
+        if cmd == "git" && args.first() == Some(&"clone") {
+            None
+        } else {
+            self.dir.as_deref()
+        }
+    }
+
+    fn record(&self, cmd: &str, args: &[&str], exit_code: Option<i32>, output: &str) {
+        self.send_log(cmd, args, exit_code, output, false);
+    }
+
+    fn record_dry_run(&self, cmd: &str, args: &[&str]) {
+        self.send_log(cmd, args, Some(0), "", true);
+    }
+
+    fn send_log(
+        &self,
+        cmd: &str,
+        args: &[&str],
+        exit_code: Option<i32>,
+        output: &str,
+        dry_run: bool,
+    ) {
+        if let Some(sink) = &self.log_sink {
+            sink(CommandLog {
+                command: format_command(cmd, args),
+                exit_code,
+                output: output.to_string(),
+                dir: self.working_dir(cmd, args).map(|d| d.display().to_string()),
+                dry_run,
+            });
+        }
+    }
+}
+
+// What the built-in backend shares with the controller it wraps
+#[cfg(feature = "builtin-git")]
+impl GitController {
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn cancel_flag(&self) -> Option<Arc<AtomicBool>> {
+        self.cancel_flag.clone()
+    }
+
+    // Logs an operation that ran in-process, shown as "gix <args>"
+    pub fn record_builtin(&self, args: &[&str], exit_code: Option<i32>, output: &str) {
+        self.send_log("gix", args, exit_code, output, false);
+    }
+
+    pub fn record_builtin_dry_run(&self, args: &[&str]) {
+        self.send_log("gix", args, Some(0), "", true);
+    }
+}
+
+impl GitBackend for GitController {
+    fn is_cancelled(&self) -> bool {
+        self.cancel_flag
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::SeqCst))
+    }
+
+    // Clones into the controller's directory
+    fn git_clone(
+        &self,
+        remote: &str,
+        branch: &str,
+        on_event: &mut dyn FnMut(&GitEvent),
+    ) -> (Option<i32>, String) {
+        let mut args = vec!["clone", "--progress", remote, "-b", branch];
+        let dest = self.dir.as_ref().map(|d| d.display().to_string());
+        if let Some(dest) = &dest {
+            args.push(dest);
+        }
+        self.exec_git_with_retry(&args, on_event)
+    }
+
+    fn git_fetch(&self, on_event: &mut dyn FnMut(&GitEvent)) -> (Option<i32>, String) {
+        self.exec_git_with_retry(&["fetch", "--progress"], on_event)
+    }
+
+    fn git_pull(
+        &self,
+        strategy: PullStrategy,
+        on_event: &mut dyn FnMut(&GitEvent),
+    ) -> (Option<i32>, String) {
+        let args: &[&str] = match strategy {
+            PullStrategy::Merge => &["pull", "--progress", "--no-rebase"],
+            PullStrategy::Rebase => &["pull", "--progress", "--rebase"],
+            PullStrategy::FfOnly => &["pull", "--progress", "--ff-only"],
+            PullStrategy::Autostash => &["pull", "--progress", "--rebase", "--autostash"],
+        };
+        self.exec_git_with_retry(args, on_event)
+    }
+
+    fn git_conflicted_files(&self) -> Vec<String> {
+        self.exec_git_args(&["diff", "--name-only", "--diff-filter=U"])
+            .lines()
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect()
+    }
+
+    // A merge or rebase the pull started and did not finish, with or without conflicts
+    fn git_pull_in_progress(&self) -> bool {
+        self.is_rebase_in_progress()
+            || self
+                .run_git(&["rev-parse", "-q", "--verify", "MERGE_HEAD"])
+                .0
+                == Some(0)
+    }
+
+    // Abort a stopped merge or rebase so the repo returns to its pre-pull state.
+    // With --autostash, `rebase --abort` also restores the stashed changes.
+    fn git_abort_pull(&self) -> (Option<i32>, String) {
+        let args: &[&str] = if self.is_rebase_in_progress() {
+            &["rebase", "--abort"]
+        } else {
+            &["merge", "--abort"]
+        };
+        self.run_git(args)
+    }
+
+    fn git_changed_files(&self) -> Vec<ChangedFile> {
+        parse_porcelain(&self.exec_git_args(&[
+            "status",
+            "--porcelain",
+            "-z",
+            "--untracked-files=all",
+        ]))
+    }
+
+    fn git_stage(&self, files: &[&ChangedFile]) {
+        let mut args = vec!["add", "-A", "--"];
+        args.extend(
+            files
+                .iter()
+                .filter(|f| f.needs_add)
+                .map(|f| f.path.as_str()),
+        );
+        if args.len() > 3 {
+            self.exec_git_args(&args);
+        }
+    }
+
+    // Undo git_stage, e.g. when the secret scan blocks the commit
+    fn git_unstage(&self, files: &[&ChangedFile]) {
+        let added: Vec<&ChangedFile> = files.iter().copied().filter(|f| f.needs_add).collect();
+        if !added.is_empty() {
+            let mut args = vec!["reset", "-q", "--"];
+            args.extend(pathspecs(&added));
+            self.exec_git_args(&args);
+        }
+    }
+
+    fn git_staged_diff(&self, files: &[&ChangedFile]) -> String {
+        let mut args = vec!["diff", "--cached", "--no-color", "--no-ext-diff", "--"];
+        args.extend(pathspecs(files));
+        self.exec_git_args(&args)
+    }
+
+    // Commit only the given files, leaving anything else in the index untouched
+    fn git_commit(&self, commit_message: &str, files: &[&ChangedFile]) -> (Option<i32>, String) {
+        let mut args = vec!["commit", "-m", commit_message, "--"];
+        args.extend(pathspecs(files));
+        self.run_git(&args)
+    }
+
+    // None when HEAD is detached
+    fn git_current_branch(&self) -> Option<String> {
+        let branch = self.exec_git_args(&["symbolic-ref", "--short", "-q", "HEAD"]);
+        let branch = branch.trim();
+        if branch.is_empty() || branch.contains("fatal") {
+            None
+        } else {
+            Some(branch.to_string())
+        }
+    }
+
+    // Commit HEAD points to; None outside a repository or before the first commit
+    fn git_head(&self) -> Option<String> {
+        let head = self.exec_git_args(&["rev-parse", "-q", "--verify", "HEAD"]);
+        let head = head.trim();
+        if !head.is_empty() && head.chars().all(|c| c.is_ascii_hexdigit()) {
+            Some(head.to_string())
+        } else {
+            None
+        }
+    }
+
+    // Moves the current branch like reset --hard, but keeps local changes and refuses
+    // if they would be overwritten. The old position stays in the reflog.
+    fn git_reset_keep(&self, commit: &str) -> String {
+        self.exec_git_args(&["reset", "--keep", commit])
+    }
+
+    // Remote-tracking branches that already contain the commit
+    fn git_remote_branches_containing(&self, commit: &str) -> Vec<String> {
+        self.exec_git_args(&[
+            "for-each-ref",
+            "--format=%(refname)",
+            "--contains",
+            commit,
+            "refs/remotes",
+        ])
+        .lines()
+        .filter(|line| !line.ends_with("/HEAD"))
+        .filter_map(|line| line.strip_prefix("refs/remotes/"))
+        .map(str::to_string)
+        .collect()
+    }
+
+    fn git_push(&self, on_event: &mut dyn FnMut(&GitEvent)) -> (Option<i32>, String) {
+        self.exec_git_with_retry(&["push"], on_event)
+    }
+
+    fn git_config(&self, name: &str, email: &str) -> (Option<i32>, String) {
+        let result = self.run_git(&["config", "user.name", name]);
+        if result.0 != Some(0) {
+            return result;
+        }
+        self.run_git(&["config", "user.email", email])
+    }
+
+    fn git_config_raw(&self, key: &str, value: &str) -> (Option<i32>, String) {
+        self.run_git(&["config", key, value])
+    }
+
+    // exec and hooks may change anything, so a dry run only records them
+    fn run_program(&self, program: &str, args: &[&str]) -> (Option<i32>, String) {
+        if self.dry_run {
+            self.record_dry_run(program, args);
+            return (Some(0), String::new());
+        }
+        self.run_process(program, args, &mut |_| {})
+    }
+}
+
+#[cfg(unix)]
+fn terminate(child: &mut Child) {
+    // SAFETY: kill(2) only sends a signal to the process id of our own child
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+    }
+}
+
+#[cfg(not(unix))]
+fn terminate(child: &mut Child) {
+    child.kill().ok();
+}
+
+// How a child watched by wait_for_child ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ChildEnd {
+    Exited,
+    TimedOut(Duration), // Killed once it ran longer than this
+    Cancelled,          // Asked to stop, and killed if it did not in time
+}
+
+// Hands what the child's pipe readers send to `on_message` until they are done, and
+// returns the child's exit code. A child that outlives `timeout` is killed; once
+// `is_cancelled` is true it is asked to stop, then killed if it does not.
+pub(crate) fn wait_for_child<T>(
+    child: &mut Child,
+    receiver: &Receiver<T>,
+    timeout: Option<Duration>,
+    is_cancelled: &dyn Fn() -> bool,
+    on_message: &mut dyn FnMut(T),
+) -> (Option<i32>, ChildEnd) {
+    let started_at = Instant::now();
+    let mut end = ChildEnd::Exited;
+    let mut terminated_at: Option<Instant> = None;
+    loop {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(message) => on_message(message),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if let Some(timeout) = timeout {
+            if started_at.elapsed() >= timeout {
+                child.kill().ok();
+                end = ChildEnd::TimedOut(timeout);
+                break;
+            }
+        }
+
+        match terminated_at {
+            None if is_cancelled() => {
+                terminate(child);
+                terminated_at = Some(Instant::now());
+                end = ChildEnd::Cancelled;
+            }
+            Some(at) if at.elapsed() >= CANCEL_GRACE_PERIOD => {
+                child.kill().ok();
+                break;
+            }
+            _ => {}
+        }
+    }
+    let exit_code = child.wait().ok().and_then(|status| status.code());
+    (exit_code, end)
+}
+
+enum PipeOutput {
+    Stdout(Vec<u8>),
+    StderrLine { bytes: Vec<u8>, redrawn: bool },
+}
+
+// Forward a child's pipe to the channel; stderr is split into lines so
+// progress can be reported while the command runs
+fn read_pipe(mut pipe: impl Read + Send + 'static, is_stderr: bool, sender: Sender<PipeOutput>) {
+    thread::spawn(move || {
+        let mut line = Vec::new();
+        let mut chunk = [0u8; 4096];
+        while let Ok(n) = pipe.read(&mut chunk) {
+            if n == 0 {
+                break;
+            }
+            if !is_stderr {
+                sender.send(PipeOutput::Stdout(chunk[..n].to_vec())).ok();
+                continue;
+            }
+            for &byte in &chunk[..n] {
+                if byte != b'\r' && byte != b'\n' {
+                    line.push(byte);
+                    continue;
+                }
+                let bytes = std::mem::take(&mut line);
+                let redrawn = byte == b'\r';
+                sender.send(PipeOutput::StderrLine { bytes, redrawn }).ok();
+            }
+        }
+        if !line.is_empty() {
+            let bytes = line;
+            sender
+                .send(PipeOutput::StderrLine {
+                    bytes,
+                    redrawn: false,
+                })
+                .ok();
+        }
+    });
+}
+
+// Make sure git fails instead of waiting for a password or host key answer
+// that nobody can type. `ssh_command` comes from batch_ssh_command.
+fn make_non_interactive(command: &mut Command, ssh_command: Option<String>) {
+    command
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env("GCM_INTERACTIVE", "never");
+    if let Some(ssh) = ssh_command {
+        command.env("GIT_SSH_COMMAND", ssh);
+    }
+}
+
+// The effective core.sshCommand in `dir`. Setting GIT_SSH_COMMAND would override it,
+// so it is read here and kept.
+fn configured_ssh_command(dir: Option<&Path>, env: &[(String, String)]) -> Option<String> {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let output = command
+        .args(["config", "--get", "core.sshCommand"])
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let ssh = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !ssh.is_empty()).then_some(ssh)
+}
+
+fn format_command(cmd: &str, args: &[&str]) -> String {
+    let mut command = cmd.to_string();
+    for arg in args {
+        if arg.is_empty() || arg.contains(char::is_whitespace) {
+            command.push_str(&format!(" '{arg}'"));
+        } else {
+            command.push(' ');
+            command.push_str(arg);
+        }
+    }
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_progress_lines() {
+        let receiving =
+            GitProgress::parse("Receiving objects:  45% (450/1000), 1.20 MiB | 2.40 MiB/s")
+                .unwrap();
+        assert_eq!(receiving.phase, "Receiving objects");
+        assert_eq!(receiving.percent, 45);
+        assert_eq!(receiving.rate.as_deref(), Some("2.40 MiB/s"));
+        assert_eq!(receiving.describe(), "Receiving objects 45% (2.40 MiB/s)");
+
+        let done =
+            GitProgress::parse("Receiving objects: 100% (1000/1000), 2.50 MiB | 2.40 MiB/s, done.")
+                .unwrap();
+        assert_eq!(done.percent, 100);
+        assert_eq!(done.rate.as_deref(), Some("2.40 MiB/s"));
+
+        let resolving = GitProgress::parse("Resolving deltas: 100% (20/20), done.").unwrap();
+        assert_eq!(resolving.percent, 100);
+        assert_eq!(resolving.rate, None);
+        assert_eq!(resolving.describe(), "Resolving deltas 100%");
+    }
+
+    #[test]
+    fn other_lines_are_not_progress() {
+        assert_eq!(
+            GitProgress::parse("remote: Counting objects: 10% (1/10)"),
+            None
+        );
+        assert_eq!(GitProgress::parse("Cloning into 'alpha'..."), None);
+        assert_eq!(GitProgress::parse("Receiving objects: done"), None);
+    }
+
+    #[test]
+    fn fraction_spans_both_phases() {
+        let progress = |line: &str| GitProgress::parse(line).unwrap().fraction();
+        assert_eq!(progress("Receiving objects:   0% (0/10)"), 0.0);
+        assert_eq!(progress("Receiving objects:  50% (5/10)"), 0.4);
+        assert_eq!(progress("Receiving objects: 100% (10/10), done."), 0.8);
+        assert_eq!(progress("Resolving deltas: 100% (4/4), done."), 1.0);
+    }
+
+    #[test]
+    fn classifies_network_failures_as_transient() {
+        assert_eq!(
+            classify_failure("fatal: unable to access 'https://x/': Could not resolve host: x"),
+            FailureKind::Transient
+        );
+        assert_eq!(
+            classify_failure(
+                "error: RPC failed; curl 56 Connection reset by peer\nfatal: early EOF"
+            ),
+            FailureKind::Transient
+        );
+    }
+
+    #[test]
+    fn permanent_causes_win_over_could_not_read() {
+        let output = "git@github.com: Permission denied (publickey).\nfatal: Could not read from remote repository.\n";
+        assert_eq!(classify_failure(output), FailureKind::Permanent);
+        let output =
+            "ERROR: Repository not found.\nfatal: Could not read from remote repository.\n";
+        assert_eq!(classify_failure(output), FailureKind::Permanent);
+        assert_eq!(
+            classify_failure("fatal: Could not read from remote repository.\n"),
+            FailureKind::Transient
+        );
+        assert_eq!(
+            classify_failure("fatal: something else"),
+            FailureKind::Permanent
+        );
+    }
+
+    #[test]
+    fn keeps_the_configured_ssh_command_in_batch_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = GitController::new().with_dir(dir.path());
+        git.run_git(&["init", "-q"]);
+        git.run_git(&["config", "core.sshCommand", "ssh -i ~/.ssh/deploy"]);
+
+        assert_eq!(
+            git.batch_ssh_command(Some(dir.path())).as_deref(),
+            Some("ssh -i ~/.ssh/deploy -o BatchMode=yes")
+        );
+        let git = git.with_env(vec![("GIT_SSH_COMMAND".to_string(), "ssh -v".to_string())]);
+        assert_eq!(
+            git.batch_ssh_command(Some(dir.path())).as_deref(),
+            Some("ssh -v -o BatchMode=yes")
+        );
+    }
+
+    #[test]
+    fn only_queries_and_the_abort_run_after_a_cancel() {
+        let dir = tempfile::tempdir().unwrap();
+        GitController::new()
+            .with_dir(dir.path())
+            .run_git(&["init", "-q"]);
+        let git = GitController::new()
+            .with_dir(dir.path())
+            .with_cancel_flag(Arc::new(AtomicBool::new(true)));
+
+        assert_eq!(git.run_git(&["status", "--porcelain"]).0, Some(0));
+        // Nothing to abort, but git ran and said so
+        assert!(git.run_git(&["merge", "--abort"]).0.is_some());
+        assert_eq!(
+            git.run_git(&["commit", "--allow-empty", "-m", "x"]),
+            (None, CANCELLED_MESSAGE.to_string())
+        );
     }
 }
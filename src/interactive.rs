@@ -1,3 +1,5 @@
+use gitp::plugin;
+use gitp::GitpSetting;
 use rustyline::completion::{Completer, Pair};
 use rustyline::error::ReadlineError;
 use rustyline::highlight::Highlighter;
@@ -11,18 +13,25 @@ pub struct GitpHelper {
 }
 
 impl GitpHelper {
-    pub fn new() -> Self {
-        GitpHelper {
-            commands: vec![
-                "clone".to_string(),
-                "pull".to_string(),
-                "push".to_string(),
-                "config user".to_string(),
-                "help".to_string(),
-                "exit".to_string(),
-                "quit".to_string(),
-            ],
-        }
+    // `custom` are the names from commands: in the settings and of the plugins
+    pub fn new(custom: impl IntoIterator<Item = String>) -> Self {
+        let mut commands = vec![
+            "clone".to_string(),
+            "fetch".to_string(),
+            "pull".to_string(),
+            "push".to_string(),
+            "sync".to_string(),
+            "config user".to_string(),
+            "exec".to_string(),
+            "history".to_string(),
+            "history show".to_string(),
+            "undo".to_string(),
+            "help".to_string(),
+            "exit".to_string(),
+            "quit".to_string(),
+        ];
+        commands.extend(custom);
+        GitpHelper { commands }
     }
 }
 
@@ -88,8 +97,9 @@ impl Validator for GitpHelper {}
 
 impl Helper for GitpHelper {}
 
-pub fn run_interactive_mode() -> rustyline::Result<Vec<String>> {
-    let helper = GitpHelper::new();
+pub fn run_interactive_mode(gitp_setting: &GitpSetting) -> rustyline::Result<Vec<String>> {
+    let plugins = plugin::list().into_iter().map(|plugin| plugin.name);
+    let helper = GitpHelper::new(gitp_setting.commands.keys().cloned().chain(plugins));
     let mut rl = Editor::new()?;
     rl.set_helper(Some(helper));
 
@@ -125,7 +135,7 @@ pub fn run_interactive_mode() -> rustyline::Result<Vec<String>> {
                         break;
                     }
                     "help" | "?" => {
-                        show_help();
+                        show_help(gitp_setting);
                     }
                     cmd => {
                         // Parse and return command
@@ -157,19 +167,75 @@ pub fn run_interactive_mode() -> rustyline::Result<Vec<String>> {
     Ok(vec![])
 }
 
-fn show_help() {
+fn show_help(gitp_setting: &GitpSetting) {
     println!("\n\x1b[1;36mAvailable Commands:\x1b[0m");
     println!("  \x1b[1;33mclone\x1b[0m [serial]        Clone all enabled repositories");
+    println!("  \x1b[1;33mfetch\x1b[0m [serial]        Fetch all enabled repositories");
     println!("  \x1b[1;33mpull\x1b[0m [serial]         Pull all enabled repositories");
+    println!("    --merge, --rebase,       Override pull_strategy from the settings");
+    println!("    --ff-only, --autostash");
     println!("  \x1b[1;33mpush\x1b[0m [serial]         Push all enabled repositories");
+    println!("    --yes, -y                Commit without reviewing the changed files");
+    println!("    --allow-protected        Allow pushing to protected_branches");
+    println!(
+        "    --allow-other-branch     Allow pushing from a branch other than the configured one"
+    );
+    println!(
+        "  \x1b[1;33msync\x1b[0m [serial]         Pull, then commit the reviewed changes and push"
+    );
+    println!("    Takes the pull and push options; skips the push where the pull failed");
     println!("  \x1b[1;33mconfig user\x1b[0m [serial]  Set user.name and user.email for all repositories");
+    println!("  \x1b[1;33mexec\x1b[0m <program> [args] Run a program in every repository");
+    println!("  \x1b[1;33mhistory\x1b[0m               List past runs");
+    println!("  \x1b[1;33mhistory show\x1b[0m <id>     Show a past run with its logs");
+    println!(
+        "  \x1b[1;33mundo\x1b[0m [run-id]          Reset repos to where they were before a run"
+    );
+    println!("                           (default: the latest pull, push or sync)");
     println!("  \x1b[1;33mhelp\x1b[0m, \x1b[1;33m?\x1b[0m              Show this help message");
     println!("  \x1b[1;33mexit\x1b[0m, \x1b[1;33mquit\x1b[0m           Exit interactive mode");
+    show_custom_commands(gitp_setting);
+    show_plugins();
     println!("\n\x1b[1;36mOptions:\x1b[0m");
     println!("  \x1b[1;33mserial\x1b[0m                 Execute sequentially (default: parallel)");
+    println!(
+        "  \x1b[1;33m--only-failed\x1b[0m          Run only the repositories that failed last time"
+    );
+    println!(
+        "  \x1b[1;33m--no-tui\x1b[0m               Print one line per status change (default without a terminal)"
+    );
+    println!("  \x1b[1;33m--json\x1b[0m                 Print a JSON report of the run instead");
+    println!("  \x1b[1;33m--dry-run\x1b[0m              Print the git commands without changing anything");
     println!("\n\x1b[1;36mTips:\x1b[0m");
     println!("  - Use \x1b[1;33mTab\x1b[0m for auto-completion");
     println!("  - Use \x1b[1;33m↑/↓\x1b[0m arrows for command history");
     println!("  - Type partial commands: '\x1b[1;33mclo\x1b[0m' + Tab → 'clone'");
     println!();
 }
+
+// commands: from the settings, with the steps each one runs
+pub fn show_custom_commands(gitp_setting: &GitpSetting) {
+    if gitp_setting.commands.is_empty() {
+        return;
+    }
+    println!("\n\x1b[1;36mCustom commands:\x1b[0m");
+    for (name, custom) in &gitp_setting.commands {
+        println!("  \x1b[1;33m{name:<21}\x1b[0m {}", custom.describe());
+    }
+}
+
+// gitp-<name> executables on PATH, run as `gitp <name>`
+pub fn show_plugins() {
+    let plugins = plugin::list();
+    if plugins.is_empty() {
+        return;
+    }
+    println!("\n\x1b[1;36mPlugins:\x1b[0m");
+    for plugin in plugins {
+        println!(
+            "  \x1b[1;33m{:<21}\x1b[0m {}",
+            plugin.name,
+            plugin.path.display()
+        );
+    }
+}
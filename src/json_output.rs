@@ -0,0 +1,96 @@
+use crate::plain_output::wait_until_done;
+use crate::tui::{RepoProgress, RepoStatus};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+// Documents held back by `collect`, printed together once it returns
+static COLLECTED: Mutex<Option<Vec<serde_json::Value>>> = Mutex::new(None);
+
+#[derive(Serialize)]
+struct RunReport<'a> {
+    command: &'a str,
+    duration: f64, // seconds
+    repos: Vec<RepoReport<'a>>,
+}
+
+#[derive(Serialize)]
+struct RepoReport<'a> {
+    name: &'a str,
+    path: &'a str,
+    status: &'static str,
+    message: &'a str,
+    duration: Option<f64>,
+    exit_code: Option<i32>, // See exit_code; None if it was killed or nothing ran
+    output: String,
+    commands: Vec<&'a str>, // With --dry-run, also the commands that were not run
+}
+
+// --json: stays silent while the workers run, then prints one document built from
+// the same RepoProgress the TUI shows. repo_paths maps repo names to their directory.
+pub fn run(
+    command: &str,
+    repos: &Arc<Mutex<Vec<RepoProgress>>>,
+    repo_paths: &HashMap<String, String>,
+) {
+    let started_at = Instant::now();
+    wait_until_done(repos);
+
+    let repos = repos.lock().unwrap();
+    let report = RunReport {
+        command,
+        duration: started_at.elapsed().as_secs_f64(),
+        repos: repos
+            .iter()
+            .map(|repo| RepoReport {
+                name: &repo.name,
+                path: repo_paths.get(&repo.name).map_or("", String::as_str),
+                status: repo.status.label(),
+                message: &repo.message,
+                duration: repo.duration.map(|d| d.as_secs_f64()),
+                exit_code: exit_code(repo),
+                output: repo.logs.iter().map(|log| log.output.as_str()).collect(),
+                commands: repo.logs.iter().map(|log| log.command.as_str()).collect(),
+            })
+            .collect(),
+    };
+
+    if let Err(e) = print(&report) {
+        eprintln!("Error: {e}");
+    }
+}
+
+// The exit code of the command that failed the repo, not of the queries or the abort
+// that ran after it; of the last command when the repo succeeded
+fn exit_code(repo: &RepoProgress) -> Option<i32> {
+    let failed = match repo.status {
+        RepoStatus::Success => None,
+        _ => repo.logs.iter().rev().find(|log| log.exit_code != Some(0)),
+    };
+    failed.or(repo.logs.last()).and_then(|log| log.exit_code)
+}
+
+// Prints one JSON document, or holds it back while `collect` runs
+pub fn print(value: &impl Serialize) -> Result<(), String> {
+    let serialize_error = |e: serde_json::Error| format!("Could not serialize: {e}");
+    if let Some(collected) = COLLECTED.lock().unwrap().as_mut() {
+        collected.push(serde_json::to_value(value).map_err(serialize_error)?);
+        return Ok(());
+    }
+    let json = serde_json::to_string_pretty(value).map_err(serialize_error)?;
+    println!("{json}");
+    Ok(())
+}
+
+// Runs `run` and prints everything it printed as one JSON array, so a command that
+// runs several others (a custom command) still prints a single document
+pub fn collect<T>(run: impl FnOnce() -> T) -> T {
+    *COLLECTED.lock().unwrap() = Some(Vec::new());
+    let result = run();
+    let documents = COLLECTED.lock().unwrap().take().unwrap_or_default();
+    if let Err(e) = print(&documents) {
+        eprintln!("Error: {e}");
+    }
+    result
+}
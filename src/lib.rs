@@ -0,0 +1,26 @@
+// gitp as a library: load the settings, pick repos and run an operation over them.
+//
+//     let setting = gitp::setting_util::load()?;
+//     let runner = Runner::new(setting.clone(), Operation::Pull(None))?
+//         .with_events(|event| println!("{event:?}"));
+//     for result in runner.run(&setting.enabled_repos()) {
+//         println!("{}: {}", result.name, result.status.label());
+//     }
+//
+// The gitp binary adds the TUI, the plain and JSON output, the run history and undo.
+
+#[cfg(feature = "builtin-git")]
+pub mod builtin_git;
+pub mod git_controller;
+pub mod plugin;
+pub mod run_control;
+pub mod runner;
+pub mod secret_scan;
+pub mod setting_util;
+pub mod workers;
+
+#[cfg(test)]
+mod fake_backend;
+
+pub use runner::{Operation, PushOptions, RepoResult, RepoStatus, RunEvent, Runner, SyncOptions};
+pub use setting_util::{GitpSetting, Repos};
@@ -1,14 +1,29 @@
-mod git_controller;
 mod interactive;
-mod setting_util;
+mod json_output;
+mod plain_output;
+mod push_review;
+mod run_history;
+mod run_state;
 mod tui;
-
-use git_controller::GitController;
+mod undo;
+
+use gitp::git_controller::{ChangedFile, GitBackend, GitController};
+use gitp::plugin::Plugin;
+use gitp::runner::{self, Operation, PushOptions, Runner, SyncOptions};
+use gitp::secret_scan::SecretScanner;
+use gitp::setting_util::{self, CustomCommand, GitpSetting, PullStrategy, Repos};
+use push_review::{PushReviewApp, RepoChanges};
+use run_history::{HeadState, RunRecord};
+use std::collections::HashMap;
 use std::env;
-use std::fs;
-use std::sync::Arc;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use tui::{update_repo_status, RepoStatus, TuiApp};
+use std::time::Instant;
+use tui::{apply_run_event, RepoStatus, TuiApp};
+use undo::UndoOutcome;
 
 fn main() {
     // Load settings
@@ -16,9 +31,13 @@ fn main() {
         Ok(s) => s,
         Err(e) => {
             eprintln!("Error: {e}");
-            std::process::exit(1);
+            std::process::exit(ExitStatus::ConfigError.code());
         }
     };
+    if let Err(e) = check_custom_commands(&gitp_setting) {
+        eprintln!("Error: {e}");
+        std::process::exit(ExitStatus::ConfigError.code());
+    }
 
     // Parse command line arguments
     let args: Vec<String> = env::args().skip(1).collect();
@@ -27,7 +46,7 @@ fn main() {
     if args.is_empty() {
         // Interactive mode
         loop {
-            match interactive::run_interactive_mode() {
+            match interactive::run_interactive_mode(&gitp_setting) {
                 Ok(cmd_args) => {
                     if cmd_args.is_empty() {
                         // User exited
@@ -46,535 +65,729 @@ fn main() {
         }
     } else {
         // One-shot mode
-        if let Err(e) = execute_command(&gitp_setting, &args) {
+        let status = execute_command(&gitp_setting, &args).unwrap_or_else(|e| {
             eprintln!("Error: {e}");
-            std::process::exit(1);
-        }
+            ExitStatus::ConfigError
+        });
+        std::process::exit(status.code());
     }
 }
 
-fn execute_command(
-    gitp_setting: &setting_util::GitpSetting,
-    args: &[String],
-) -> Result<(), String> {
+fn execute_command(gitp_setting: &GitpSetting, args: &[String]) -> Result<ExitStatus, String> {
     if args.is_empty() {
-        return Ok(());
+        return Ok(ExitStatus::Success);
     }
 
-    let command = &args[0];
-    let is_serial = args.len() > 1 && args[1] == "serial";
+    let command = match canonical_command(&args[0]) {
+        Some("help") => {
+            show_help(gitp_setting);
+            return Ok(ExitStatus::Success);
+        }
+        Some(command) => command,
+        None => {
+            if let Some(custom) = gitp_setting.commands.get(&args[0]) {
+                return run_custom_command(gitp_setting, custom, &args[1..]);
+            }
+            return match Plugin::find(&args[0]) {
+                Some(plugin) => run_plugin(gitp_setting, plugin, args),
+                None => Err(format!("Unknown command: {}", args[0])),
+            };
+        }
+    };
+    let (option_args, program) = if command == "exec" {
+        split_exec_args(&args[1..])
+    } else {
+        (&args[1..], &[][..])
+    };
+    if command == "exec" && program.is_empty() {
+        return Err("Usage: gitp exec [options] <program> [args...]".to_string());
+    }
+    let options = parse_options(option_args)?;
+    match command {
+        "history" => return show_history(&options),
+        "undo" => return undo_command(&options),
+        _ => {}
+    }
+    let is_serial = options.is_serial;
+    let use_tui = options.use_tui();
 
-    // Collect enabled repositories
-    let enabled_repos: Vec<_> = gitp_setting.repos.iter().filter(|r| r.enabled).collect();
+    let Some(mut enabled_repos) = select_repos(gitp_setting, command, &options) else {
+        return Ok(ExitStatus::Success);
+    };
 
-    if enabled_repos.is_empty() {
-        println!("No enabled repositories found in configuration.");
-        return Ok(());
+    // Push and sync show what will be committed and let the user deselect repos or files.
+    // Sync commits what was picked here once its pull is done.
+    let mut push_changes = Vec::new();
+    if command == "push" || command == "sync" {
+        // Invalid secret_scan patterns are reported before the review
+        if gitp_setting.secret_scan.enabled {
+            SecretScanner::new(&gitp_setting.secret_scan)?;
+        }
+        push_changes = collect_push_changes(gitp_setting, &enabled_repos);
+        // A dry run commits nothing, so there is nothing to review
+        let review = !options.yes && !options.dry_run;
+        if review && !use_tui {
+            return Err(format!(
+                "Reviewing the changes needs a terminal; use --yes to {command} without review"
+            ));
+        }
+        if review {
+            match PushReviewApp::new(push_changes).run() {
+                Ok(Some(reviewed)) => push_changes = reviewed,
+                Ok(None) => {
+                    println!(
+                        "{} cancelled.",
+                        if command == "sync" { "Sync" } else { "Push" }
+                    );
+                    return Ok(ExitStatus::Cancelled);
+                }
+                Err(e) => return Err(format!("TUI error: {e:?}")),
+            }
+        }
+        push_changes.retain(|c| c.selected);
+        enabled_repos.retain(|r| {
+            let name = r.name();
+            push_changes.iter().any(|c| c.name == name)
+        });
+        if enabled_repos.is_empty() {
+            println!("No repositories selected for {command}.");
+            return Ok(ExitStatus::Success);
+        }
     }
 
+    let operation = match command {
+        "clone" => Operation::Clone,
+        "fetch" => Operation::Fetch,
+        "pull" => Operation::Pull(options.pull_strategy),
+        "push" => Operation::Push(PushOptions {
+            files: selected_files(&push_changes),
+            allow_protected: options.allow_protected,
+            allow_other_branch: options.allow_other_branch,
+        }),
+        "exec" => Operation::Exec(program.to_vec()),
+        "sync" => Operation::Sync(SyncOptions {
+            pull_strategy: options.pull_strategy,
+            files: selected_files(&push_changes),
+            allow_protected: options.allow_protected,
+            allow_other_branch: options.allow_other_branch,
+        }),
+        // config - apply all configs from YAML, config user - user.name and user.email only
+        _ => match options.positional.first().map(String::as_str) {
+            None => Operation::Config,
+            Some("user" | "u" | "usr") => Operation::ConfigUser,
+            Some(subcommand) => {
+                return Err(format!("Unknown subcommand: config {subcommand}"));
+            }
+        },
+    };
+
     // Extract repository names for TUI
-    let repo_names: Vec<String> = enabled_repos
-        .iter()
-        .map(|r| extract_repo_name(&r.remote))
-        .collect();
+    let repo_names: Vec<String> = enabled_repos.iter().map(|r| r.name()).collect();
+    let repo_paths: HashMap<String, String> =
+        enabled_repos.iter().map(|r| (r.name(), r.path())).collect();
 
     // Create TUI app
     let mut tui_app = TuiApp::new(repo_names);
     let repos_handle = tui_app.get_repos_handle();
 
-    // Spawn worker threads based on command
-    match command.as_str() {
-        "clone" | "clo" | "cl" => {
-            spawn_clone_workers(gitp_setting, &enabled_repos, repos_handle.clone());
+    // The runner's events fill the RepoProgress that the TUI and the outputs read
+    let events_handle = repos_handle.clone();
+    let runner = Runner::new(gitp_setting.clone(), operation)?
+        .with_serial(is_serial)
+        .with_dry_run(options.dry_run)
+        .with_events(move |event| apply_run_event(&events_handle, event));
+
+    if let Err(e) = runner.before_all() {
+        eprintln!("Error: {e}");
+        return Ok(ExitStatus::HookFailed);
+    }
+    let start = RunStart::now(&repo_paths);
+    // Workers of the run and of its retries, joined by finish_run
+    let workers = Arc::new(Mutex::new(runner.spawn(&enabled_repos)));
+    let retry_workers = Arc::clone(&workers);
+
+    let hook_runner = runner.clone();
+    let cancel_runner = runner.clone();
+    tui_app.set_cancel_handler(move || cancel_runner.cancel());
+
+    // The TUI requeues failed and cancelled repos for a retry
+    tui_app.set_retry_handler(move |names| {
+        runner.resume();
+        let handles = runner.spawn(&runner.setting().select_repos(&names));
+        retry_workers.lock().unwrap().extend(handles);
+    });
+
+    let join_workers = || {
+        let handles = std::mem::take(&mut *workers.lock().unwrap());
+        for handle in handles {
+            handle.join().ok();
         }
-        "pull" | "pul" | "pu" => {
-            spawn_pull_workers(gitp_setting, &enabled_repos, repos_handle.clone());
+    };
+    let status = finish_run(
+        command,
+        args,
+        &options,
+        tui_app,
+        &repo_paths,
+        start,
+        join_workers,
+    );
+
+    let results: Vec<(String, RepoStatus)> = repos_handle
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|r| (r.name.clone(), r.status.clone()))
+        .collect();
+    if let Err(e) = hook_runner.after_all(&results) {
+        eprintln!("Warning: {e}");
+    }
+
+    Ok(status)
+}
+
+// The enabled repos, or with --only-failed those that failed in the previous run.
+// None when there is nothing to run, which has been reported.
+fn select_repos<'a>(
+    gitp_setting: &'a GitpSetting,
+    command: &str,
+    options: &CommandOptions,
+) -> Option<Vec<&'a Repos>> {
+    let mut enabled_repos = gitp_setting.enabled_repos();
+
+    if options.only_failed {
+        let failed = run_state::load_failed(command);
+        enabled_repos.retain(|r| failed.contains(&r.name()));
+        if enabled_repos.is_empty() {
+            println!("No repositories failed in the previous {command} run.");
+            return None;
         }
-        "push" | "pus" | "ps" => {
-            spawn_push_workers(gitp_setting, &enabled_repos, repos_handle.clone());
+    }
+
+    if enabled_repos.is_empty() {
+        println!("No enabled repositories found in configuration.");
+        return None;
+    }
+    Some(enabled_repos)
+}
+
+// When a run started and the HEAD of each repo then, for the run history
+struct RunStart {
+    started_at: chrono::DateTime<chrono::Local>,
+    started: Instant,
+    heads_before: HashMap<String, HeadState>,
+}
+
+impl RunStart {
+    // Before any worker starts
+    fn now(repo_paths: &HashMap<String, String>) -> Self {
+        RunStart {
+            started_at: chrono::Local::now(),
+            started: Instant::now(),
+            heads_before: capture_heads(repo_paths),
         }
-        "config" | "conf" | "cfg" => {
-            // Check for subcommand
-            if args.len() < 2 {
-                // No subcommand - apply all configs from YAML
-                spawn_config_all_workers(gitp_setting, &enabled_repos, repos_handle.clone());
-            } else {
-                let subcommand = &args[1];
-                if subcommand == "user" || subcommand == "u" || subcommand == "usr" {
-                    // config user - apply user.name and user.email only
-                    let is_serial_config = args.len() > 2 && args[2] == "serial";
-                    spawn_config_user_workers(gitp_setting, &enabled_repos, repos_handle.clone());
-
-                    if let Err(e) = tui_app.run(!is_serial_config) {
-                        return Err(format!("TUI error: {e:?}"));
-                    }
-                    return Ok(());
-                } else if subcommand == "serial" {
-                    // config serial - apply all configs in serial mode
-                    spawn_config_all_workers(gitp_setting, &enabled_repos, repos_handle.clone());
+    }
+}
 
-                    if let Err(e) = tui_app.run(false) {
-                        return Err(format!("TUI error: {e:?}"));
-                    }
-                    return Ok(());
-                } else {
-                    return Err(format!("Unknown subcommand: config {subcommand}"));
+// Shows the run in the TUI, as plain lines or as JSON until it ends, then remembers
+// what failed and records it in the run history
+fn finish_run(
+    command: &str,
+    args: &[String],
+    options: &CommandOptions,
+    mut tui_app: TuiApp,
+    repo_paths: &HashMap<String, String>,
+    start: RunStart,
+    join_workers: impl FnOnce(),
+) -> ExitStatus {
+    let repos_handle = tui_app.get_repos_handle();
+
+    // Run TUI, or print plain lines when there is no terminal to draw on
+    let result = if options.json {
+        json_output::run(command, &repos_handle, repo_paths);
+        Ok(())
+    } else if options.dry_run {
+        plain_output::print_dry_run(&repos_handle);
+        Ok(())
+    } else if options.use_tui() {
+        tui_app.run()
+    } else {
+        plain_output::run(&repos_handle);
+        Ok(())
+    };
+
+    // Let the workers end before their results are read. After a force quit some are
+    // still running and are left behind.
+    let all_done = repos_handle
+        .lock()
+        .unwrap()
+        .iter()
+        .all(|r| r.status.is_done());
+    if all_done {
+        join_workers();
+    }
+
+    // A dry run changed nothing, so it is neither remembered nor recorded
+    let repos = repos_handle.lock().unwrap();
+    if !options.dry_run {
+        // Remember what failed for --only-failed
+        let failed: Vec<String> = repos
+            .iter()
+            .filter(|r| r.status == RepoStatus::Failed)
+            .map(|r| r.name.clone())
+            .collect();
+        if let Err(e) = run_state::save_failed(command, &failed) {
+            eprintln!("Warning: could not save the failed repositories: {e}");
+        }
+
+        // After a force quit some workers may still be running, so HEAD is not final
+        let heads_after = if repos.iter().all(|r| r.status.is_done()) {
+            capture_heads(repo_paths)
+        } else {
+            HashMap::new()
+        };
+        let mut record = RunRecord::new(
+            args.join(" "),
+            start.started_at,
+            start.started.elapsed(),
+            &repos,
+            repo_paths,
+            &start.heads_before,
+            &heads_after,
+        );
+        if let Err(e) = run_history::save(&mut record) {
+            eprintln!("Warning: could not save the run history: {e}");
+        }
+    }
+
+    // The workers' results still decide the exit code if the TUI itself failed
+    if let Err(e) = result {
+        eprintln!("Error: TUI error: {e:?}");
+    }
+
+    ExitStatus::from_results(&repos)
+}
+
+// gitp <name> for a gitp-<name> on PATH. gitp keeps --only-failed, --no-tui and --json;
+// every other argument, --dry-run included, goes to the plugin.
+fn run_plugin(
+    gitp_setting: &GitpSetting,
+    plugin: Plugin,
+    args: &[String],
+) -> Result<ExitStatus, String> {
+    let (own_args, plugin_args): (Vec<String>, Vec<String>) = args[1..]
+        .iter()
+        .cloned()
+        .partition(|arg| matches!(arg.as_str(), "--only-failed" | "--no-tui" | "--json"));
+    let options = parse_options(&own_args)?;
+    let command = plugin.name.clone();
+
+    let Some(enabled_repos) = select_repos(gitp_setting, &command, &options) else {
+        return Ok(ExitStatus::Success);
+    };
+    let repo_names: Vec<String> = enabled_repos.iter().map(|r| r.name()).collect();
+    let repo_paths: HashMap<String, String> =
+        enabled_repos.iter().map(|r| (r.name(), r.path())).collect();
+
+    let mut tui_app = TuiApp::new(repo_names);
+    let events_handle = tui_app.get_repos_handle();
+    let start = RunStart::now(&repo_paths);
+
+    // One process for all repos; its progress events fill the RepoProgress
+    let cancel = Arc::new(AtomicBool::new(false));
+    let plugin_cancel = Arc::clone(&cancel);
+    let repos: Vec<Repos> = enabled_repos.into_iter().cloned().collect();
+    let worker = thread::spawn(move || {
+        let repos: Vec<&Repos> = repos.iter().collect();
+        plugin.run(&plugin_args, &repos, &plugin_cancel, &|event| {
+            apply_run_event(&events_handle, event)
+        });
+    });
+    tui_app.set_cancel_handler(move || cancel.store(true, Ordering::SeqCst));
+
+    Ok(finish_run(
+        &command,
+        args,
+        &options,
+        tui_app,
+        &repo_paths,
+        start,
+        || {
+            worker.join().ok();
+        },
+    ))
+}
+
+// A custom command may not hide a built-in one, and its steps must be built-in commands
+fn check_custom_commands(gitp_setting: &GitpSetting) -> Result<(), String> {
+    for (name, custom) in &gitp_setting.commands {
+        if canonical_command(name).is_some() {
+            return Err(format!("commands: {name} is already a built-in command"));
+        }
+        for step in custom.steps() {
+            match step.split_whitespace().next() {
+                Some(first) if canonical_command(first).is_some() => {}
+                Some(first) => {
+                    return Err(format!(
+                        "commands: {name}: {first} is not a built-in command"
+                    ));
                 }
+                None => return Err(format!("commands: {name} has an empty step")),
             }
+        }
+    }
+    Ok(())
+}
 
-            // Run TUI for config without subcommand
-            if let Err(e) = tui_app.run(true) {
-                return Err(format!("TUI error: {e:?}"));
-            }
-            return Ok(());
+// Runs the steps of a custom command in order, each like its own command line. The
+// arguments it was given go to every step, e.g. `gitp up serial`. A step that does
+// not fully succeed stops the ones after it. With --json the reports of the steps
+// are printed as one array.
+fn run_custom_command(
+    gitp_setting: &GitpSetting,
+    custom: &CustomCommand,
+    extra: &[String],
+) -> Result<ExitStatus, String> {
+    let json = extra.iter().any(|arg| arg == "--json");
+    if json {
+        return json_output::collect(|| run_custom_steps(gitp_setting, custom, extra, json));
+    }
+    run_custom_steps(gitp_setting, custom, extra, json)
+}
+
+fn run_custom_steps(
+    gitp_setting: &GitpSetting,
+    custom: &CustomCommand,
+    extra: &[String],
+    json: bool,
+) -> Result<ExitStatus, String> {
+    let mut status = ExitStatus::Success;
+    for step in custom.steps() {
+        // Right after the command name, so that exec still reads them as gitp options
+        let mut words = step.split_whitespace().map(String::from);
+        let args: Vec<String> = words
+            .next()
+            .into_iter()
+            .chain(extra.iter().cloned())
+            .chain(words)
+            .collect();
+        if !json {
+            println!("→ gitp {}", args.join(" "));
         }
-        "help" | "?" => {
-            show_help();
-            return Ok(());
+        status = execute_command(gitp_setting, &args)?;
+        if status != ExitStatus::Success {
+            break;
         }
-        _ => {
-            return Err(format!("Unknown command: {command}"));
+    }
+    Ok(status)
+}
+
+// gitp's own options come before the program: exec serial --no-tui cargo check --all
+fn split_exec_args(args: &[String]) -> (&[String], &[String]) {
+    let program_at = args
+        .iter()
+        .position(|arg| arg != "serial" && !arg.starts_with('-'))
+        .unwrap_or(args.len());
+    args.split_at(program_at)
+}
+
+// Exit code of one-shot mode
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExitStatus {
+    Success,
+    PartialFailure, // Some repos failed or hit a conflict
+    TotalFailure,   // Every repo failed or hit a conflict
+    ConfigError,    // Invalid settings or command line; nothing was run
+    HookFailed,     // The before_all hook failed; nothing was run
+    Cancelled,      // Cancelled by the user, or quit before all repos finished
+}
+
+impl ExitStatus {
+    fn code(self) -> i32 {
+        match self {
+            ExitStatus::Success => 0,
+            ExitStatus::PartialFailure => 1,
+            ExitStatus::TotalFailure => 2,
+            ExitStatus::ConfigError => 3,
+            ExitStatus::HookFailed => 4,
+            ExitStatus::Cancelled => 130,
         }
     }
 
-    // Run TUI
-    if let Err(e) = tui_app.run(!is_serial) {
-        return Err(format!("TUI error: {e:?}"));
+    fn from_results(repos: &[tui::RepoProgress]) -> Self {
+        if repos
+            .iter()
+            .any(|r| r.status == RepoStatus::Cancelled || !r.status.is_done())
+        {
+            return ExitStatus::Cancelled;
+        }
+        let failed = repos
+            .iter()
+            .filter(|r| matches!(r.status, RepoStatus::Failed | RepoStatus::Conflict))
+            .count();
+        if failed == 0 {
+            ExitStatus::Success
+        } else if failed == repos.len() {
+            ExitStatus::TotalFailure
+        } else {
+            ExitStatus::PartialFailure
+        }
     }
+}
 
-    Ok(())
+#[derive(Clone)]
+struct CommandOptions {
+    positional: Vec<String>,
+    is_serial: bool,
+    pull_strategy: Option<PullStrategy>,
+    yes: bool,
+    allow_protected: bool,
+    allow_other_branch: bool,
+    only_failed: bool,
+    no_tui: bool,
+    json: bool,
+    dry_run: bool,
+}
+
+impl CommandOptions {
+    // Plain lines instead when there is no terminal to draw on
+    fn use_tui(&self) -> bool {
+        !self.no_tui && !self.json && !self.dry_run && io::stdout().is_terminal()
+    }
 }
 
-fn show_help() {
+fn parse_options(args: &[String]) -> Result<CommandOptions, String> {
+    let mut options = CommandOptions {
+        positional: Vec::new(),
+        is_serial: false,
+        pull_strategy: None,
+        yes: false,
+        allow_protected: false,
+        allow_other_branch: false,
+        only_failed: false,
+        no_tui: false,
+        json: false,
+        dry_run: false,
+    };
+
+    for arg in args {
+        if arg == "serial" {
+            options.is_serial = true;
+        } else if let Some(strategy) = PullStrategy::from_flag(arg) {
+            options.pull_strategy = Some(strategy);
+        } else if arg == "--yes" || arg == "-y" {
+            options.yes = true;
+        } else if arg == "--allow-protected" {
+            options.allow_protected = true;
+        } else if arg == "--allow-other-branch" {
+            options.allow_other_branch = true;
+        } else if arg == "--only-failed" {
+            options.only_failed = true;
+        } else if arg == "--no-tui" {
+            options.no_tui = true;
+        } else if arg == "--json" {
+            options.json = true;
+        } else if arg == "--dry-run" {
+            options.dry_run = true;
+        } else if arg.starts_with("--") {
+            return Err(format!("Unknown option: {arg}"));
+        } else {
+            options.positional.push(arg.clone());
+        }
+    }
+
+    Ok(options)
+}
+
+fn show_help(gitp_setting: &GitpSetting) {
     println!("\n\x1b[1;36mgitp\x1b[0m - Git Multiple Repository Manager\n");
     println!("\x1b[1;36mUsage:\x1b[0m");
     println!("  gitp                   Start interactive mode");
-    println!("  gitp <command> [opts]  Execute command and exit\n");
+    println!("  gitp <command> [opts]  Execute command and exit");
+    println!("  gitp <plugin> [args]   Run gitp-<plugin> from PATH over the repositories\n");
     println!("\x1b[1;36mCommands:\x1b[0m");
     println!("  \x1b[1;33mclone\x1b[0m [serial]        Clone all enabled repositories");
+    println!("  \x1b[1;33mfetch\x1b[0m [serial]        Fetch all enabled repositories");
     println!("  \x1b[1;33mpull\x1b[0m [serial]         Pull all enabled repositories");
+    println!("    --merge, --rebase,       Override pull_strategy from the settings");
+    println!("    --ff-only, --autostash");
     println!("  \x1b[1;33mpush\x1b[0m [serial]         Push all enabled repositories");
+    println!("    --yes, -y                Commit without reviewing the changed files");
+    println!("    --allow-protected        Allow pushing to protected_branches");
+    println!(
+        "    --allow-other-branch     Allow pushing from a branch other than the configured one"
+    );
+    println!(
+        "  \x1b[1;33msync\x1b[0m [serial]         Pull, then commit the reviewed changes and push"
+    );
+    println!("    Takes the pull and push options; skips the push where the pull failed");
     println!("  \x1b[1;33mconfig user\x1b[0m [serial]  Set user.name and user.email for all repositories");
-    println!("  \x1b[1;33mhelp\x1b[0m                  Show this help message\n");
+    println!("  \x1b[1;33mexec\x1b[0m <program> [args] Run a program in every repository");
+    println!("  \x1b[1;33mhistory\x1b[0m               List past runs");
+    println!("  \x1b[1;33mhistory show\x1b[0m <id>     Show a past run with its logs");
+    println!(
+        "  \x1b[1;33mundo\x1b[0m [run-id]          Reset repos to where they were before a run"
+    );
+    println!("                           (default: the latest pull, push or sync)");
+    println!("  \x1b[1;33mhelp\x1b[0m                  Show this help message");
+    interactive::show_custom_commands(gitp_setting);
+    interactive::show_plugins();
+    println!();
     println!("\x1b[1;36mOptions:\x1b[0m");
+    println!("  \x1b[1;33mserial\x1b[0m                 Execute sequentially (default: parallel)");
+    println!(
+        "  \x1b[1;33m--only-failed\x1b[0m          Run only the repositories that failed last time"
+    );
     println!(
-        "  \x1b[1;33mserial\x1b[0m                 Execute sequentially (default: parallel)\n"
+        "  \x1b[1;33m--no-tui\x1b[0m               Print one line per status change (default without a terminal)"
     );
+    println!("  \x1b[1;33m--json\x1b[0m                 Print a JSON report of the run instead");
+    println!("  \x1b[1;33m--dry-run\x1b[0m              Print the git commands without changing anything\n");
+    println!("\x1b[1;36mExit codes:\x1b[0m");
+    println!("  0    All repositories succeeded");
+    println!("  1    Some repositories failed or hit a conflict");
+    println!("  2    All repositories failed or hit a conflict");
+    println!("  3    Invalid settings or command line");
+    println!("  4    The before_all hook failed");
+    println!("  130  Cancelled\n");
     println!("\x1b[1;36mShortcuts:\x1b[0m");
     println!("  clo, cl  → clone");
+    println!("  fet, fe  → fetch");
     println!("  pul, pu  → pull");
     println!("  pus, ps  → push");
+    println!("  syn, sy  → sync");
     println!("  conf, cfg → config");
+    println!("  hist     → history");
     println!("  u, usr   → user (for config subcommand)\n");
 }
 
-fn spawn_clone_workers(
-    setting: &setting_util::GitpSetting,
-    repos: &[&setting_util::Repos],
-    repos_handle: Arc<std::sync::Mutex<Vec<tui::RepoProgress>>>,
-) {
-    for repo in repos {
-        let repo_clone = (*repo).clone();
-        let user_name = setting.user.name.clone();
-        let user_email = setting.user.email.clone();
-        let repos_handle = Arc::clone(&repos_handle);
-        let repo_name = extract_repo_name(&repo.remote);
-
-        thread::spawn(move || {
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Running,
-                "Starting...",
-                10,
-            );
-
-            let git = GitController::new();
-
-            // Create group directory
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Running,
-                "Creating directory...",
-                20,
-            );
-            if let Err(e) = fs::create_dir_all(&repo_clone.group) {
-                update_repo_status(
-                    &repos_handle,
-                    &repo_name,
-                    RepoStatus::Failed,
-                    &format!("Error: {e}"),
-                    100,
-                );
-                return;
-            }
-
-            // Change to group directory
-            let original_dir = env::current_dir().unwrap();
-            if let Err(e) = env::set_current_dir(&repo_clone.group) {
-                update_repo_status(
-                    &repos_handle,
-                    &repo_name,
-                    RepoStatus::Failed,
-                    &format!("Error: {e}"),
-                    100,
-                );
-                return;
-            }
-
-            // Clone
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Running,
-                "Cloning...",
-                40,
-            );
-            let result = git.git_clone(&repo_clone.remote, &repo_clone.branch);
-
-            // Change into cloned repo
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Running,
-                "Configuring...",
-                80,
-            );
-            if let Err(e) = env::set_current_dir(extract_repo_name(&repo_clone.remote)) {
-                update_repo_status(
-                    &repos_handle,
-                    &repo_name,
-                    RepoStatus::Failed,
-                    &format!("Error: {e}"),
-                    100,
-                );
-                env::set_current_dir(original_dir).ok();
-                return;
-            }
-
-            git.git_config(&user_name, &user_email);
-            env::set_current_dir(original_dir).ok();
-
-            if result.contains("fatal") || result.contains("error") {
-                update_repo_status(&repos_handle, &repo_name, RepoStatus::Failed, "Failed", 100);
-            } else {
-                update_repo_status(&repos_handle, &repo_name, RepoStatus::Success, "Done", 100);
-            }
-        });
+fn canonical_command(name: &str) -> Option<&'static str> {
+    match name {
+        "clone" | "clo" | "cl" => Some("clone"),
+        "fetch" | "fet" | "fe" => Some("fetch"),
+        "pull" | "pul" | "pu" => Some("pull"),
+        "push" | "pus" | "ps" => Some("push"),
+        "sync" | "syn" | "sy" => Some("sync"),
+        "config" | "conf" | "cfg" => Some("config"),
+        "history" | "hist" => Some("history"),
+        "undo" => Some("undo"),
+        "exec" => Some("exec"),
+        "help" | "?" => Some("help"),
+        _ => None,
     }
 }
 
-fn spawn_pull_workers(
-    setting: &setting_util::GitpSetting,
-    repos: &[&setting_util::Repos],
-    repos_handle: Arc<std::sync::Mutex<Vec<tui::RepoProgress>>>,
-) {
-    for repo in repos {
-        let repo_clone = (*repo).clone();
-        let user_name = setting.user.name.clone();
-        let user_email = setting.user.email.clone();
-        let repos_handle = Arc::clone(&repos_handle);
-        let repo_name = extract_repo_name(&repo.remote);
-
-        thread::spawn(move || {
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Running,
-                "Starting...",
-                10,
-            );
-
-            let git = GitController::new();
-            let repo_path = format!(
-                "{}/{}",
-                repo_clone.group,
-                extract_repo_name(&repo_clone.remote)
-            );
-
-            let original_dir = env::current_dir().unwrap();
-            if let Err(e) = env::set_current_dir(&repo_path) {
-                update_repo_status(
-                    &repos_handle,
-                    &repo_name,
-                    RepoStatus::Failed,
-                    &format!("Error: {e}"),
-                    100,
-                );
-                return;
-            }
-
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Running,
-                "Configuring...",
-                30,
-            );
-            git.git_config(&user_name, &user_email);
-
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Running,
-                "Pulling...",
-                50,
-            );
-            let result = git.git_pull();
-
-            env::set_current_dir(original_dir).ok();
-
-            if result.contains("fatal") || result.contains("error") {
-                update_repo_status(&repos_handle, &repo_name, RepoStatus::Failed, "Failed", 100);
-            } else {
-                update_repo_status(&repos_handle, &repo_name, RepoStatus::Success, "Done", 100);
-            }
-        });
+// HEAD of every repo that exists, keyed by repo name
+fn capture_heads(repo_paths: &HashMap<String, String>) -> HashMap<String, HeadState> {
+    let mut heads = HashMap::new();
+    for (name, repo_path) in repo_paths {
+        // Without .git, git would report the HEAD of an enclosing repository
+        if !Path::new(repo_path).join(".git").exists() {
+            continue;
+        }
+        let git = GitController::new().with_dir(repo_path);
+        if let Some(commit) = git.git_head() {
+            let branch = git.git_current_branch();
+            heads.insert(name.clone(), HeadState { commit, branch });
+        }
     }
+    heads
 }
 
-fn spawn_push_workers(
-    setting: &setting_util::GitpSetting,
-    repos: &[&setting_util::Repos],
-    repos_handle: Arc<std::sync::Mutex<Vec<tui::RepoProgress>>>,
-) {
-    let commit_message = setting
-        .comments
-        .get("default")
-        .unwrap_or(&"update.".to_string())
-        .clone();
-
-    for repo in repos {
-        let repo_clone = (*repo).clone();
-        let user_name = setting.user.name.clone();
-        let user_email = setting.user.email.clone();
-        let repos_handle = Arc::clone(&repos_handle);
-        let repo_name = extract_repo_name(&repo.remote);
-        let commit_msg = commit_message.clone();
-
-        thread::spawn(move || {
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Running,
-                "Starting...",
-                10,
-            );
-
-            let git = GitController::new();
-            let repo_path = format!(
-                "{}/{}",
-                repo_clone.group,
-                extract_repo_name(&repo_clone.remote)
-            );
-
-            let original_dir = env::current_dir().unwrap();
-            if let Err(e) = env::set_current_dir(&repo_path) {
-                update_repo_status(
-                    &repos_handle,
-                    &repo_name,
-                    RepoStatus::Failed,
-                    &format!("Error: {e}"),
-                    100,
-                );
-                return;
+// history lists the recorded runs, history show <id> prints one of them
+fn show_history(options: &CommandOptions) -> Result<ExitStatus, String> {
+    let positional: Vec<&str> = options.positional.iter().map(String::as_str).collect();
+    match positional.as_slice() {
+        [] => {
+            let records = run_history::load_all();
+            if options.json {
+                json_output::print(&records)?;
+            } else {
+                run_history::print_list(&records);
             }
-
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Running,
-                "Configuring...",
-                20,
-            );
-            git.git_config(&user_name, &user_email);
-
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Running,
-                "Adding files...",
-                40,
-            );
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Running,
-                "Committing...",
-                60,
-            );
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Running,
-                "Pushing...",
-                80,
-            );
-
-            let result = git.git_push(&commit_msg);
-
-            env::set_current_dir(original_dir).ok();
-
-            if result.contains("fatal") || result.contains("error") {
-                update_repo_status(&repos_handle, &repo_name, RepoStatus::Failed, "Failed", 100);
+        }
+        ["show", id] => {
+            let record = run_history::load(id)?;
+            if options.json {
+                json_output::print(&record)?;
             } else {
-                update_repo_status(&repos_handle, &repo_name, RepoStatus::Success, "Done", 100);
+                run_history::print_run(&record);
             }
-        });
+        }
+        ["show"] => return Err("Usage: gitp history show <id>".to_string()),
+        [subcommand, ..] => return Err(format!("Unknown subcommand: history {subcommand}")),
     }
+    Ok(ExitStatus::Success)
 }
 
-fn spawn_config_all_workers(
-    setting: &setting_util::GitpSetting,
-    repos: &[&setting_util::Repos],
-    repos_handle: Arc<std::sync::Mutex<Vec<tui::RepoProgress>>>,
-) {
-    for repo in repos {
-        let repo_clone = (*repo).clone();
-        let user_name = setting.user.name.clone();
-        let user_email = setting.user.email.clone();
-        let configs = setting.config.clone();
-        let repos_handle = Arc::clone(&repos_handle);
-        let repo_name = extract_repo_name(&repo.remote);
-
-        thread::spawn(move || {
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Running,
-                "Starting...",
-                10,
-            );
-
-            let git = GitController::new();
-            let repo_path = format!(
-                "{}/{}",
-                repo_clone.group,
-                extract_repo_name(&repo_clone.remote)
-            );
-
-            let original_dir = env::current_dir().unwrap();
-            if let Err(e) = env::set_current_dir(&repo_path) {
-                update_repo_status(
-                    &repos_handle,
-                    &repo_name,
-                    RepoStatus::Failed,
-                    &format!("Error: {e}"),
-                    100,
-                );
-                return;
-            }
+// undo [run-id] resets the repos of a run, by default the latest pull, push or sync
+fn undo_command(options: &CommandOptions) -> Result<ExitStatus, String> {
+    let record = match options.positional.as_slice() {
+        [] => run_history::load_all()
+            .into_iter()
+            .rev()
+            .find(|record| {
+                let name = record.command.split_whitespace().next().unwrap_or("");
+                matches!(canonical_command(name), Some("pull" | "push" | "sync"))
+            })
+            .ok_or("No pull, push or sync run recorded yet. See `gitp history`.")?,
+        [id] => run_history::load(id)?,
+        [_, extra, ..] => return Err(format!("Unexpected argument: {extra}")),
+    };
 
-            // Apply user.name and user.email
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Running,
-                "Setting user...",
-                20,
-            );
-            git.git_config(&user_name, &user_email);
-
-            // Apply all configs from YAML
-            let total_configs = configs.len();
-            for (i, (key, value)) in configs.iter().enumerate() {
-                let progress = 20 + ((i + 1) * 70 / total_configs.max(1)) as u16;
-                update_repo_status(
-                    &repos_handle,
-                    &repo_name,
-                    RepoStatus::Running,
-                    &format!("Setting {key}..."),
-                    progress,
-                );
-                git.git_config_raw(key, value);
+    let name = record.command.split_whitespace().next().unwrap_or("");
+    let pushed = matches!(canonical_command(name), Some("push" | "sync"));
+    let results = undo::undo_run(&record, pushed);
+    if options.json {
+        json_output::print(&results)?;
+    } else {
+        println!("Undoing run {}: gitp {}", record.id, record.command);
+        for result in &results {
+            let color = match result.outcome {
+                UndoOutcome::Reset => "32",
+                UndoOutcome::Unchanged => "90",
+                UndoOutcome::Refused => "31",
+            };
+            println!("  \x1b[{color}m{}\x1b[0m: {}", result.name, result.message);
+            if let Some(warning) = &result.warning {
+                println!("    \x1b[33mWarning:\x1b[0m {warning}");
             }
-
-            env::set_current_dir(original_dir).ok();
-
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Success,
-                "Configured",
-                100,
-            );
-        });
+        }
     }
-}
 
-fn spawn_config_user_workers(
-    setting: &setting_util::GitpSetting,
-    repos: &[&setting_util::Repos],
-    repos_handle: Arc<std::sync::Mutex<Vec<tui::RepoProgress>>>,
-) {
-    for repo in repos {
-        let repo_clone = (*repo).clone();
-        let user_name = setting.user.name.clone();
-        let user_email = setting.user.email.clone();
-        let repos_handle = Arc::clone(&repos_handle);
-        let repo_name = extract_repo_name(&repo.remote);
-
-        thread::spawn(move || {
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Running,
-                "Starting...",
-                10,
-            );
-
-            let git = GitController::new();
-            let repo_path = format!(
-                "{}/{}",
-                repo_clone.group,
-                extract_repo_name(&repo_clone.remote)
-            );
-
-            let original_dir = env::current_dir().unwrap();
-            if let Err(e) = env::set_current_dir(&repo_path) {
-                update_repo_status(
-                    &repos_handle,
-                    &repo_name,
-                    RepoStatus::Failed,
-                    &format!("Error: {e}"),
-                    100,
-                );
-                return;
-            }
+    let refused = results
+        .iter()
+        .filter(|r| r.outcome == UndoOutcome::Refused)
+        .count();
+    Ok(if refused == 0 {
+        ExitStatus::Success
+    } else if refused == results.len() {
+        ExitStatus::TotalFailure
+    } else {
+        ExitStatus::PartialFailure
+    })
+}
 
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Running,
-                "Setting user.name...",
-                40,
-            );
-            git.git_config(&user_name, &user_email);
-
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Running,
-                "Setting user.email...",
-                80,
-            );
-
-            env::set_current_dir(original_dir).ok();
-
-            update_repo_status(
-                &repos_handle,
-                &repo_name,
-                RepoStatus::Success,
-                "Configured",
-                100,
-            );
-        });
-    }
+fn collect_push_changes(setting: &GitpSetting, repos: &[&Repos]) -> Vec<RepoChanges> {
+    repos
+        .iter()
+        .map(|repo| RepoChanges {
+            name: repo.name(),
+            selected: true,
+            files: runner::changed_files(setting, repo),
+        })
+        .collect()
 }
 
-fn extract_repo_name(remote_url: &str) -> String {
-    let parts: Vec<&str> = remote_url.split('/').collect();
-    let last_part = parts.last().unwrap_or(&"");
-    last_part.trim_end_matches(".git").to_string()
+// The reviewed files to commit, by repo name
+fn selected_files(changes: &[RepoChanges]) -> HashMap<String, Vec<ChangedFile>> {
+    changes
+        .iter()
+        .map(|c| {
+            (
+                c.name.clone(),
+                c.selected_files().into_iter().cloned().collect(),
+            )
+        })
+        .collect()
 }
@@ -0,0 +1,108 @@
+use crate::tui::{format_duration, RepoProgress, RepoStatus};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Used instead of the TUI in cron, CI logs and pipes: the workers report through
+// update_repo_status as usual and every status change becomes one line
+pub fn run(repos: &Arc<Mutex<Vec<RepoProgress>>>) {
+    let started_at = Instant::now();
+    let mut reported: Vec<RepoStatus> = Vec::new();
+
+    loop {
+        let repos = repos.lock().unwrap();
+        reported.resize(repos.len(), RepoStatus::Pending);
+        for (repo, last) in repos.iter().zip(reported.iter_mut()) {
+            if repo.status != *last {
+                print_change(repo);
+                *last = repo.status.clone();
+            }
+        }
+        let all_done = repos.iter().all(|r| r.status.is_done());
+        drop(repos);
+
+        if all_done {
+            break;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    print_summary(&repos.lock().unwrap(), started_at.elapsed());
+}
+
+// --dry-run: once the workers are done, list each repo's commands like a shell script.
+// Queries that ran to decide what to do are marked as such.
+pub fn print_dry_run(repos: &Arc<Mutex<Vec<RepoProgress>>>) {
+    wait_until_done(repos);
+
+    for repo in repos.lock().unwrap().iter() {
+        println!(
+            "# {} ({}: {})",
+            repo.name,
+            repo.status.label(),
+            repo.message
+        );
+        let mut dir = None;
+        for log in &repo.logs {
+            if log.dir != dir {
+                dir = log.dir.clone();
+                println!("cd {}", dir.as_deref().unwrap_or("."));
+            }
+            if log.dry_run {
+                println!("{}", log.command);
+            } else {
+                println!("{}  # query, ran during the dry run", log.command);
+            }
+        }
+        println!();
+    }
+    println!("Dry run: nothing was changed.");
+}
+
+pub fn wait_until_done(repos: &Arc<Mutex<Vec<RepoProgress>>>) {
+    while !repos.lock().unwrap().iter().all(|r| r.status.is_done()) {
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn print_change(repo: &RepoProgress) {
+    let duration = match repo.duration {
+        Some(duration) if repo.status.is_done() => format!(" ({})", format_duration(duration)),
+        _ => String::new(),
+    };
+    println!(
+        "{} {:9} {}: {}{}",
+        timestamp(),
+        repo.status.label(),
+        repo.name,
+        repo.message,
+        duration
+    );
+}
+
+fn print_summary(repos: &[RepoProgress], elapsed: Duration) {
+    let count = |status: RepoStatus| repos.iter().filter(|r| r.status == status).count();
+    println!(
+        "{} finished in {}: {} succeeded, {} failed, {} conflicted, {} cancelled",
+        timestamp(),
+        format_duration(elapsed),
+        count(RepoStatus::Success),
+        count(RepoStatus::Failed),
+        count(RepoStatus::Conflict),
+        count(RepoStatus::Cancelled)
+    );
+    for repo in repos.iter().filter(|r| r.status != RepoStatus::Success) {
+        println!(
+            "  {:9} {}: {}",
+            repo.status.label(),
+            repo.name,
+            repo.message
+        );
+    }
+}
+
+fn timestamp() -> String {
+    chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
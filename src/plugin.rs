@@ -0,0 +1,296 @@
+use crate::git_controller::{wait_for_child, ChildEnd, CommandLog};
+use crate::runner::{RepoStatus, RunEvent};
+use crate::setting_util::Repos;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+// External commands: any gitp-<name> executable on PATH runs as `gitp <name>`.
+//
+// The plugin gets the selected repos as a JSON array, on stdin and in GITP_REPOS:
+//
+//     [{"name": "alpha", "path": "/work/ws/alpha", "remote": "...", "branch": "main", "group": "ws"}]
+//
+// Each stdout line that is a JSON object reports on one repo, e.g.
+//
+//     {"repo": "alpha", "status": "running", "message": "Building...", "progress": 40}
+//
+// status is pending, running (the default), success, failed, conflict or cancelled;
+// message and progress (0-100) are optional. Any other output ends up in the repo logs.
+const PREFIX: &str = "gitp-";
+
+pub struct Plugin {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct PluginEvent {
+    repo: String,
+    status: Option<String>,
+    #[serde(default)]
+    message: String,
+    progress: Option<u16>,
+}
+
+impl Plugin {
+    pub fn find(name: &str) -> Option<Plugin> {
+        list().into_iter().find(|plugin| plugin.name == name)
+    }
+
+    // Runs until the plugin exits or `cancel` is raised. The repos it did not finish end
+    // up Success if it exited with 0 and Failed otherwise, so the run always completes.
+    pub fn run(
+        &self,
+        args: &[String],
+        repos: &[&Repos],
+        cancel: &AtomicBool,
+        on_event: &dyn Fn(RunEvent),
+    ) {
+        let repos_json = repos_json(repos);
+        let command_line = format!("{PREFIX}{} {}", self.name, args.join(" "))
+            .trim_end()
+            .to_string();
+        let spawned = Command::new(&self.path)
+            .args(args)
+            .env("GITP_REPOS", &repos_json)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                let message = format!("Failed: could not run {}: {e}", self.path.display());
+                finish(
+                    repos,
+                    &HashSet::new(),
+                    RepoStatus::Failed,
+                    &message,
+                    on_event,
+                );
+                return;
+            }
+        };
+
+        // Written on its own thread, so a plugin that never reads stdin cannot block the run
+        let mut stdin = child.stdin.take().unwrap();
+        thread::spawn(move || stdin.write_all(repos_json.as_bytes()).ok());
+
+        let (sender, receiver) = mpsc::channel();
+        forward_lines(child.stdout.take().unwrap(), false, sender.clone());
+        forward_lines(child.stderr.take().unwrap(), true, sender);
+
+        let mut finished = HashSet::new();
+        let mut output = String::new();
+        let (exit_code, end) = wait_for_child(
+            &mut child,
+            &receiver,
+            None,
+            &|| cancel.load(Ordering::SeqCst),
+            &mut |(is_stderr, line)| match (!is_stderr).then(|| parse_event(&line)).flatten() {
+                Some((repo, status, message, progress)) => {
+                    if status.is_done() {
+                        finished.insert(repo.clone());
+                    }
+                    on_event(RunEvent::Status {
+                        repo,
+                        status,
+                        message,
+                        progress,
+                    });
+                }
+                None => {
+                    output.push_str(&line);
+                    output.push('\n');
+                }
+            },
+        );
+
+        let log = CommandLog {
+            command: command_line,
+            exit_code,
+            output,
+            dir: None,
+            dry_run: false,
+        };
+        for repo in repos {
+            on_event(RunEvent::Log {
+                repo: repo.name(),
+                log: log.clone(),
+            });
+        }
+        let (status, message) = match exit_code {
+            _ if end == ChildEnd::Cancelled => (RepoStatus::Cancelled, "Cancelled".to_string()),
+            Some(0) => (RepoStatus::Success, "Done".to_string()),
+            Some(code) => (
+                RepoStatus::Failed,
+                format!("Failed: {PREFIX}{} exited with {code}", self.name),
+            ),
+            None => (
+                RepoStatus::Failed,
+                format!("Failed: {PREFIX}{} was killed", self.name),
+            ),
+        };
+        finish(repos, &finished, status, &message, on_event);
+    }
+}
+
+// Every gitp-<name> on PATH, by name. Like in a shell, the first directory wins.
+pub fn list() -> Vec<Plugin> {
+    let mut plugins: Vec<Plugin> = Vec::new();
+    let Some(paths) = env::var_os("PATH") else {
+        return plugins;
+    };
+    for dir in env::split_paths(&paths) {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            let Some(name) = plugin_name(&path) else {
+                continue;
+            };
+            if is_executable(&path) && !plugins.iter().any(|p| p.name == name) {
+                plugins.push(Plugin { name, path });
+            }
+        }
+    }
+    plugins.sort_by(|a, b| a.name.cmp(&b.name));
+    plugins
+}
+
+// "gitp-foo" -> "foo", "gitp-foo.exe" on Windows
+fn plugin_name(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?.strip_prefix(PREFIX)?;
+    let name = if cfg!(windows) {
+        name.strip_suffix(".exe")?
+    } else {
+        name
+    };
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+// Paths are absolute, so the plugin may run from anywhere
+fn repos_json(repos: &[&Repos]) -> String {
+    let cwd = env::current_dir().unwrap_or_default();
+    let repos: Vec<serde_json::Value> = repos
+        .iter()
+        .map(|repo| {
+            serde_json::json!({
+                "name": repo.name(),
+                "path": cwd.join(repo.path()),
+                "remote": repo.remote,
+                "branch": repo.branch,
+                "group": repo.group,
+            })
+        })
+        .collect();
+    serde_json::Value::from(repos).to_string()
+}
+
+// None for a line that is not a progress event
+fn parse_event(line: &str) -> Option<(String, RepoStatus, String, u16)> {
+    let event: PluginEvent = serde_json::from_str(line).ok()?;
+    let status = match event.status.as_deref() {
+        None => RepoStatus::Running,
+        Some(label) => RepoStatus::from_label(label)?,
+    };
+    let progress = event
+        .progress
+        .unwrap_or(if status.is_done() { 100 } else { 0 })
+        .min(100);
+    let message = match (event.message.is_empty(), &status) {
+        (true, RepoStatus::Success) => "Done".to_string(),
+        (true, RepoStatus::Failed) => "Failed".to_string(),
+        (true, RepoStatus::Conflict) => "Conflict".to_string(),
+        (true, RepoStatus::Cancelled) => "Cancelled".to_string(),
+        _ => event.message,
+    };
+    Some((event.repo, status, message, progress))
+}
+
+fn forward_lines(
+    pipe: impl Read + Send + 'static,
+    is_stderr: bool,
+    sender: Sender<(bool, String)>,
+) {
+    thread::spawn(move || {
+        for line in BufReader::new(pipe).lines().map_while(Result::ok) {
+            if sender.send((is_stderr, line)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+fn finish(
+    repos: &[&Repos],
+    finished: &HashSet<String>,
+    status: RepoStatus,
+    message: &str,
+    on_event: &dyn Fn(RunEvent),
+) {
+    for repo in repos.iter().map(|repo| repo.name()) {
+        if !finished.contains(&repo) {
+            on_event(RunEvent::Status {
+                repo,
+                status: status.clone(),
+                message: message.to_string(),
+                progress: 100,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_progress_events() {
+        assert_eq!(
+            parse_event(r#"{"repo": "alpha", "message": "Building...", "progress": 40}"#),
+            Some((
+                "alpha".to_string(),
+                RepoStatus::Running,
+                "Building...".to_string(),
+                40
+            ))
+        );
+        assert_eq!(
+            parse_event(r#"{"repo": "alpha", "status": "failed"}"#),
+            Some((
+                "alpha".to_string(),
+                RepoStatus::Failed,
+                "Failed".to_string(),
+                100
+            ))
+        );
+    }
+
+    #[test]
+    fn other_lines_are_not_events() {
+        assert_eq!(parse_event("Compiling alpha"), None);
+        assert_eq!(parse_event(r#"{"name": "alpha"}"#), None);
+        assert_eq!(parse_event(r#"{"repo": "alpha", "status": "done"}"#), None);
+    }
+}
@@ -0,0 +1,243 @@
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use gitp::git_controller::{ChangeKind, ChangedFile};
+use ratatui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    Frame, Terminal,
+};
+use std::io;
+
+#[derive(Debug, Clone)]
+pub struct RepoChanges {
+    pub name: String,
+    pub selected: bool,
+    pub files: Vec<ChangedFile>,
+}
+
+impl RepoChanges {
+    pub fn selected_files(&self) -> Vec<&ChangedFile> {
+        self.files.iter().filter(|f| f.selected).collect()
+    }
+}
+
+enum Row {
+    Repo(usize),
+    File(usize, usize),
+}
+
+pub struct PushReviewApp {
+    changes: Vec<RepoChanges>,
+    state: ListState,
+}
+
+impl PushReviewApp {
+    pub fn new(changes: Vec<RepoChanges>) -> Self {
+        let mut state = ListState::default();
+        state.select(Some(0));
+        PushReviewApp { changes, state }
+    }
+
+    // Returns the reviewed changes, or None if the user cancelled the push
+    pub fn run(mut self) -> Result<Option<Vec<RepoChanges>>, io::Error> {
+        enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        execute!(stdout, EnterAlternateScreen)?;
+        let backend = CrosstermBackend::new(stdout);
+        let mut terminal = Terminal::new(backend)?;
+
+        let res = self.run_app(&mut terminal);
+
+        disable_raw_mode()?;
+        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        terminal.show_cursor()?;
+
+        match res? {
+            true => Ok(Some(self.changes)),
+            false => Ok(None),
+        }
+    }
+
+    fn run_app<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> io::Result<bool> {
+        loop {
+            terminal.draw(|f| self.ui(f))?;
+
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Enter | KeyCode::Char('y') => return Ok(true),
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+                    KeyCode::Up | KeyCode::Char('k') => self.move_cursor(-1),
+                    KeyCode::Down | KeyCode::Char('j') => self.move_cursor(1),
+                    KeyCode::Char(' ') => self.toggle_current(),
+                    KeyCode::Char('a') => self.toggle_all(),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn rows(&self) -> Vec<Row> {
+        let mut rows = Vec::new();
+        for (i, repo) in self.changes.iter().enumerate() {
+            rows.push(Row::Repo(i));
+            for j in 0..repo.files.len() {
+                rows.push(Row::File(i, j));
+            }
+        }
+        rows
+    }
+
+    fn move_cursor(&mut self, delta: isize) {
+        let len = self.rows().len();
+        if len == 0 {
+            return;
+        }
+        let current = self.state.selected().unwrap_or(0) as isize;
+        let next = (current + delta).clamp(0, len as isize - 1);
+        self.state.select(Some(next as usize));
+    }
+
+    fn toggle_current(&mut self) {
+        let rows = self.rows();
+        let Some(row) = self.state.selected().and_then(|i| rows.get(i)) else {
+            return;
+        };
+        match *row {
+            Row::Repo(i) => {
+                let repo = &mut self.changes[i];
+                repo.selected = !repo.selected;
+            }
+            Row::File(i, j) => {
+                let repo = &mut self.changes[i];
+                repo.files[j].selected = !repo.files[j].selected;
+                // Selecting a file implies its repo
+                if repo.files[j].selected {
+                    repo.selected = true;
+                }
+            }
+        }
+    }
+
+    fn toggle_all(&mut self) {
+        let select = !self.changes.iter().all(|r| r.selected);
+        for repo in &mut self.changes {
+            repo.selected = select;
+        }
+    }
+
+    fn ui(&mut self, f: &mut Frame) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(2)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Min(0),
+                Constraint::Length(3),
+            ])
+            .split(f.area());
+
+        // Header
+        let header = Paragraph::new(Line::from(vec![
+            Span::styled(
+                "gitp",
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" - Review changes before push"),
+        ]))
+        .block(Block::default().borders(Borders::ALL));
+        f.render_widget(header, chunks[0]);
+
+        // Repositories and their changed files
+        let items: Vec<ListItem> = self
+            .rows()
+            .iter()
+            .map(|row| match *row {
+                Row::Repo(i) => {
+                    let repo = &self.changes[i];
+                    let summary = if repo.files.is_empty() {
+                        "no changes, push only".to_string()
+                    } else {
+                        format!("{}/{} files", repo.selected_files().len(), repo.files.len())
+                    };
+                    ListItem::new(Line::from(vec![
+                        Span::raw(checkbox(repo.selected)),
+                        Span::styled(
+                            format!("{:40}", repo.name),
+                            Style::default()
+                                .fg(Color::Cyan)
+                                .add_modifier(Modifier::BOLD),
+                        ),
+                        Span::styled(format!(" {summary}"), Style::default().fg(Color::White)),
+                    ]))
+                }
+                Row::File(i, j) => {
+                    let repo = &self.changes[i];
+                    let file = &repo.files[j];
+                    let (label, color) = match file.kind {
+                        ChangeKind::Staged => ("staged   ", Color::Green),
+                        ChangeKind::Modified => ("modified ", Color::Yellow),
+                        ChangeKind::Untracked => ("untracked", Color::Red),
+                    };
+                    let path = match &file.orig_path {
+                        Some(orig) => format!("{orig} -> {}", file.path),
+                        None => file.path.clone(),
+                    };
+                    let (style, label_style) = if repo.selected {
+                        (Style::default(), Style::default().fg(color))
+                    } else {
+                        let dimmed = Style::default().fg(Color::DarkGray);
+                        (dimmed, dimmed)
+                    };
+                    ListItem::new(Line::from(vec![
+                        Span::raw("    "),
+                        Span::styled(checkbox(file.selected), style),
+                        Span::styled(format!("{label} "), label_style),
+                        Span::styled(path, style),
+                    ]))
+                }
+            })
+            .collect();
+
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(" Changes to commit "),
+            )
+            .highlight_style(Style::default().bg(Color::DarkGray));
+        f.render_stateful_widget(list, chunks[1], &mut self.state);
+
+        // Footer
+        let footer = Paragraph::new(Line::from(vec![
+            Span::styled("Space", Style::default().fg(Color::Yellow)),
+            Span::raw(": toggle | "),
+            Span::styled("a", Style::default().fg(Color::Yellow)),
+            Span::raw(": toggle all repos | "),
+            Span::styled("Enter", Style::default().fg(Color::Yellow)),
+            Span::raw(": commit and push | "),
+            Span::styled("q", Style::default().fg(Color::Yellow)),
+            Span::raw(": cancel"),
+        ]))
+        .block(Block::default().borders(Borders::ALL));
+        f.render_widget(footer, chunks[2]);
+    }
+}
+
+fn checkbox(selected: bool) -> &'static str {
+    if selected {
+        "[x] "
+    } else {
+        "[ ] "
+    }
+}
@@ -0,0 +1,81 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+// Shared by the workers of one run: limits how many repos run at once,
+// lets the TUI cancel the run and tells the workers about --dry-run
+pub struct RunControl {
+    cancelled: Arc<AtomicBool>,
+    dry_run: bool,
+    max_running: usize,
+    running: Mutex<usize>,
+    slot_freed: Condvar,
+}
+
+// Held by a worker while it runs; frees the slot for the next repo on drop
+pub struct RunSlot {
+    control: Arc<RunControl>,
+}
+
+impl RunControl {
+    pub fn new(is_parallel: bool, dry_run: bool) -> Arc<RunControl> {
+        Arc::new(RunControl {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            dry_run,
+            max_running: if is_parallel { usize::MAX } else { 1 },
+            running: Mutex::new(0),
+            slot_freed: Condvar::new(),
+        })
+    }
+
+    // Waits for a free slot. None once the run is cancelled, so repos that
+    // have not started yet are never scheduled.
+    pub fn acquire(self: &Arc<Self>) -> Option<RunSlot> {
+        let mut running = self.running.lock().unwrap();
+        while *running >= self.max_running && !self.is_cancelled() {
+            running = self.slot_freed.wait(running).unwrap();
+        }
+        if self.is_cancelled() {
+            return None;
+        }
+        *running += 1;
+        Some(RunSlot {
+            control: Arc::clone(self),
+        })
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        let _running = self.running.lock().unwrap();
+        self.slot_freed.notify_all();
+    }
+
+    // Allow scheduling again, e.g. before retrying cancelled repos
+    pub fn resume(&self) {
+        self.cancelled.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn is_parallel(&self) -> bool {
+        self.max_running > 1
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    // For GitController::with_cancel_flag
+    pub fn cancel_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.cancelled)
+    }
+}
+
+impl Drop for RunSlot {
+    fn drop(&mut self) {
+        let mut running = self.control.running.lock().unwrap();
+        *running -= 1;
+        self.control.slot_freed.notify_all();
+    }
+}
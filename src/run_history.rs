@@ -0,0 +1,206 @@
+use crate::run_state::state_dir;
+use crate::tui::{format_duration, RepoProgress, RepoStatus};
+use gitp::git_controller::CommandLog;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Older runs are removed when a new one is saved
+const MAX_RUNS: usize = 200;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeadState {
+    pub commit: String,
+    pub branch: Option<String>, // None when HEAD is detached
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoRecord {
+    pub name: String,
+    pub path: String,
+    pub status: String,
+    pub message: String,
+    pub duration: Option<f64>, // seconds
+    pub head_before: Option<HeadState>,
+    pub head_after: Option<HeadState>,
+    pub logs: Vec<CommandLog>,
+}
+
+// One invocation of a command, saved as runs/<id>.yaml in the state directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub id: String,
+    pub command: String,
+    pub started_at: String,
+    pub duration: f64, // seconds
+    pub repos: Vec<RepoRecord>,
+}
+
+impl RunRecord {
+    pub fn new(
+        command: String,
+        started_at: chrono::DateTime<chrono::Local>,
+        duration: Duration,
+        repos: &[RepoProgress],
+        repo_paths: &HashMap<String, String>,
+        heads_before: &HashMap<String, HeadState>,
+        heads_after: &HashMap<String, HeadState>,
+    ) -> Self {
+        RunRecord {
+            id: started_at.format("%Y%m%d-%H%M%S").to_string(),
+            command,
+            started_at: started_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            duration: duration.as_secs_f64(),
+            repos: repos
+                .iter()
+                .map(|repo| RepoRecord {
+                    name: repo.name.clone(),
+                    path: repo_paths.get(&repo.name).cloned().unwrap_or_default(),
+                    status: repo.status.label().to_string(),
+                    message: repo.message.clone(),
+                    duration: repo.duration.map(|d| d.as_secs_f64()),
+                    head_before: heads_before.get(&repo.name).cloned(),
+                    head_after: heads_after.get(&repo.name).cloned(),
+                    logs: repo.logs.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    fn count(&self, status: RepoStatus) -> usize {
+        self.repos
+            .iter()
+            .filter(|r| r.status == status.label())
+            .count()
+    }
+}
+
+fn runs_dir() -> PathBuf {
+    state_dir().join("runs")
+}
+
+// Runs started within the same second get a numbered id
+pub fn save(record: &mut RunRecord) -> Result<(), Box<dyn Error>> {
+    let dir = runs_dir();
+    fs::create_dir_all(&dir)?;
+
+    let base_id = record.id.clone();
+    let mut n = 2;
+    while dir.join(format!("{}.yaml", record.id)).exists() {
+        record.id = format!("{base_id}-{n}");
+        n += 1;
+    }
+    fs::write(
+        dir.join(format!("{}.yaml", record.id)),
+        serde_yaml::to_string(record)?,
+    )?;
+
+    let mut ids = run_ids();
+    if ids.len() > MAX_RUNS {
+        for id in ids.drain(..ids.len() - MAX_RUNS) {
+            fs::remove_file(dir.join(format!("{id}.yaml"))).ok();
+        }
+    }
+    Ok(())
+}
+
+// Oldest first
+fn run_ids() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(runs_dir()) else {
+        return Vec::new();
+    };
+    let mut ids: Vec<String> = entries
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            name.strip_suffix(".yaml").map(str::to_string)
+        })
+        .collect();
+    ids.sort();
+    ids
+}
+
+pub fn load(id: &str) -> Result<RunRecord, String> {
+    let yaml_text = fs::read_to_string(runs_dir().join(format!("{id}.yaml")))
+        .map_err(|_| format!("No run with id {id}. See `gitp history`."))?;
+    serde_yaml::from_str(&yaml_text).map_err(|e| format!("Could not read run {id}: {e}"))
+}
+
+// Runs that can no longer be parsed are skipped
+pub fn load_all() -> Vec<RunRecord> {
+    run_ids().iter().filter_map(|id| load(id).ok()).collect()
+}
+
+pub fn print_list(records: &[RunRecord]) {
+    if records.is_empty() {
+        println!("No runs recorded yet.");
+        return;
+    }
+    for record in records {
+        println!(
+            "\x1b[1;33m{:20}\x1b[0m {}  {:30} \x1b[32m✓ {}\x1b[0m \x1b[31m✗ {}\x1b[0m \x1b[35m⚠ {}\x1b[0m \x1b[90m⊘ {}\x1b[0m",
+            record.id,
+            record.started_at,
+            record.command,
+            record.count(RepoStatus::Success),
+            record.count(RepoStatus::Failed),
+            record.count(RepoStatus::Conflict),
+            record.count(RepoStatus::Cancelled)
+        );
+    }
+}
+
+pub fn print_run(record: &RunRecord) {
+    println!(
+        "\n\x1b[1;36mRun {}\x1b[0m: gitp {}",
+        record.id, record.command
+    );
+    println!(
+        "Started {}, took {}\n",
+        record.started_at,
+        format_duration(Duration::from_secs_f64(record.duration))
+    );
+
+    for repo in &record.repos {
+        let duration = repo
+            .duration
+            .map(|d| format!(" ({})", format_duration(Duration::from_secs_f64(d))))
+            .unwrap_or_default();
+        println!(
+            "\x1b[1;33m{}\x1b[0m {} [{}]{}",
+            repo.name, repo.path, repo.status, duration
+        );
+        println!("  {}", repo.message);
+        println!(
+            "  HEAD: {} -> {}",
+            describe_head(&repo.head_before),
+            describe_head(&repo.head_after)
+        );
+        for log in &repo.logs {
+            let exit = match log.exit_code {
+                Some(code) => format!("exit {code}"),
+                None => "killed".to_string(),
+            };
+            println!("  \x1b[36m$ {}\x1b[0m [{exit}]", log.command);
+            for line in log.output.lines() {
+                println!("    {line}");
+            }
+        }
+        println!();
+    }
+}
+
+fn describe_head(head: &Option<HeadState>) -> String {
+    match head {
+        Some(head) => {
+            let short = &head.commit[..head.commit.len().min(7)];
+            match &head.branch {
+                Some(branch) => format!("{branch} {short}"),
+                None => format!("detached {short}"),
+            }
+        }
+        None => "none".to_string(),
+    }
+}
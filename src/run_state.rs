@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+// ~/.local/state/gitp on Linux, the local data directory elsewhere
+pub fn state_dir() -> PathBuf {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .map(|mut path| {
+            path.push("gitp");
+            path
+        })
+        .unwrap_or_else(|| PathBuf::from(".gitp"))
+}
+
+fn last_failed_file() -> PathBuf {
+    state_dir().join("last_failed.yaml")
+}
+
+// Repositories that failed in the previous run of the command
+pub fn load_failed(command: &str) -> Vec<String> {
+    let Ok(yaml_text) = fs::read_to_string(last_failed_file()) else {
+        return Vec::new();
+    };
+    let mut failed: HashMap<String, Vec<String>> =
+        serde_yaml::from_str(&yaml_text).unwrap_or_default();
+    failed.remove(command).unwrap_or_default()
+}
+
+pub fn save_failed(command: &str, repo_names: &[String]) -> Result<(), Box<dyn Error>> {
+    let path = last_failed_file();
+    let mut failed: HashMap<String, Vec<String>> = fs::read_to_string(&path)
+        .ok()
+        .and_then(|yaml_text| serde_yaml::from_str(&yaml_text).ok())
+        .unwrap_or_default();
+    failed.insert(command.to_string(), repo_names.to_vec());
+
+    fs::create_dir_all(state_dir())?;
+    fs::write(path, serde_yaml::to_string(&failed)?)?;
+    Ok(())
+}
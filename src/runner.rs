@@ -0,0 +1,544 @@
+use crate::git_controller::{ChangedFile, CommandLog, GitBackend, GitController};
+use crate::run_control::RunControl;
+use crate::secret_scan::SecretScanner;
+use crate::setting_util::{Backend, GitpSetting, PullStrategy, Repos};
+use crate::workers;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RepoStatus {
+    Pending,
+    Running,
+    Success,
+    Failed,
+    Conflict,
+    Cancelled,
+}
+
+impl RepoStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            RepoStatus::Pending => "pending",
+            RepoStatus::Running => "running",
+            RepoStatus::Success => "success",
+            RepoStatus::Failed => "failed",
+            RepoStatus::Conflict => "conflict",
+            RepoStatus::Cancelled => "cancelled",
+        }
+    }
+
+    // The inverse of label, e.g. for plugin events
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label {
+            "pending" => Some(RepoStatus::Pending),
+            "running" => Some(RepoStatus::Running),
+            "success" => Some(RepoStatus::Success),
+            "failed" => Some(RepoStatus::Failed),
+            "conflict" => Some(RepoStatus::Conflict),
+            "cancelled" => Some(RepoStatus::Cancelled),
+            _ => None,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        matches!(
+            self,
+            RepoStatus::Success | RepoStatus::Failed | RepoStatus::Conflict | RepoStatus::Cancelled
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Operation {
+    Clone,
+    Fetch,
+    Pull(Option<PullStrategy>), // None uses pull_strategy from the settings
+    Push(PushOptions),
+    Config,            // user.name, user.email and every key under config:
+    ConfigUser,        // user.name and user.email only
+    Exec(Vec<String>), // A program and its arguments, run in every repo
+    Sync(SyncOptions), // Pull, then commit what is dirty and push
+}
+
+impl Operation {
+    // The command name used for timeout: in the settings
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Clone => "clone",
+            Operation::Fetch => "fetch",
+            Operation::Pull(_) => "pull",
+            Operation::Push(_) => "push",
+            Operation::Config | Operation::ConfigUser => "config",
+            Operation::Exec(_) => "exec",
+            Operation::Sync(_) => "sync",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PushOptions {
+    pub files: HashMap<String, Vec<ChangedFile>>, // Files to commit by repo name; other repos are only pushed
+    pub allow_protected: bool,
+    pub allow_other_branch: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SyncOptions {
+    pub pull_strategy: Option<PullStrategy>, // None uses pull_strategy from the settings
+    pub files: HashMap<String, Vec<ChangedFile>>, // Like PushOptions::files
+    pub allow_protected: bool,
+    pub allow_other_branch: bool,
+}
+
+// Sent from the worker threads while a run is in progress
+#[derive(Debug, Clone)]
+pub enum RunEvent {
+    Status {
+        repo: String,
+        status: RepoStatus,
+        message: String,
+        progress: u16, // 0-100
+    },
+    Log {
+        repo: String,
+        log: CommandLog,
+    },
+}
+
+// How one repo ended up
+#[derive(Debug, Clone)]
+pub struct RepoResult {
+    pub name: String,
+    pub path: String,
+    pub status: RepoStatus,
+    pub message: String,
+    pub duration: Duration,
+    pub logs: Vec<CommandLog>,
+}
+
+type EventHandler = Arc<dyn Fn(RunEvent) + Send + Sync>;
+type Logs = Arc<Mutex<Vec<CommandLog>>>;
+
+// Runs one operation over a set of repos, one worker thread per repo. Cloning a Runner
+// shares its cancellation, so the clone can be handed to a UI.
+#[derive(Clone)]
+pub struct Runner {
+    setting: Arc<GitpSetting>,
+    operation: Arc<Operation>,
+    secret_scanner: Option<Arc<SecretScanner>>,
+    control: Arc<RunControl>,
+    on_event: EventHandler,
+}
+
+impl Runner {
+    // Fails if the secret_scan patterns of a push or sync are invalid
+    pub fn new(setting: GitpSetting, operation: Operation) -> Result<Self, String> {
+        let secret_scanner = match operation {
+            Operation::Push(_) | Operation::Sync(_) if setting.secret_scan.enabled => {
+                Some(Arc::new(SecretScanner::new(&setting.secret_scan)?))
+            }
+            _ => None,
+        };
+        Ok(Runner {
+            setting: Arc::new(setting),
+            operation: Arc::new(operation),
+            secret_scanner,
+            control: RunControl::new(true, false),
+            on_event: Arc::new(|_| {}),
+        })
+    }
+
+    // One repo at a time instead of all in parallel
+    pub fn with_serial(mut self, serial: bool) -> Self {
+        self.control = RunControl::new(!serial, self.control.is_dry_run());
+        self
+    }
+
+    // Record the commands that would change something instead of running them
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.control = RunControl::new(self.control.is_parallel(), dry_run);
+        self
+    }
+
+    // Receive every status change and command log as it happens
+    pub fn with_events(mut self, on_event: impl Fn(RunEvent) + Send + Sync + 'static) -> Self {
+        self.on_event = Arc::new(on_event);
+        self
+    }
+
+    pub fn setting(&self) -> &GitpSetting {
+        &self.setting
+    }
+
+    // Stops running git commands; repos that have not started end up Cancelled
+    pub fn cancel(&self) {
+        self.control.cancel();
+    }
+
+    // Lets repos run again after a cancel, e.g. for a retry
+    pub fn resume(&self) {
+        self.control.resume();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.control.is_cancelled()
+    }
+
+    // before_all from hooks:, to call before spawn or run. A failure carries the hook's
+    // output, and the run should not start. Skipped in a dry run.
+    pub fn before_all(&self) -> Result<(), String> {
+        self.run_global_hook("before_all", Vec::new())
+    }
+
+    // after_all from hooks:, to call once every repo has finished. It gets the repo
+    // names by result in GITP_SUCCEEDED, GITP_FAILED, GITP_CONFLICT and GITP_CANCELLED.
+    pub fn after_all(&self, results: &[(String, RepoStatus)]) -> Result<(), String> {
+        let names = |status: RepoStatus| {
+            results
+                .iter()
+                .filter(|(_, s)| *s == status)
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let env = vec![
+            ("GITP_SUCCEEDED".to_string(), names(RepoStatus::Success)),
+            ("GITP_FAILED".to_string(), names(RepoStatus::Failed)),
+            ("GITP_CONFLICT".to_string(), names(RepoStatus::Conflict)),
+            ("GITP_CANCELLED".to_string(), names(RepoStatus::Cancelled)),
+        ];
+        self.run_global_hook("after_all", env)
+    }
+
+    fn run_global_hook(&self, event: &str, mut env: Vec<(String, String)>) -> Result<(), String> {
+        let Some(script) = self.setting.hooks.get(event) else {
+            return Ok(());
+        };
+        if self.control.is_dry_run() {
+            return Ok(());
+        }
+        env.push((
+            "GITP_COMMAND".to_string(),
+            self.operation.name().to_string(),
+        ));
+
+        let output = Arc::new(Mutex::new(String::new()));
+        let sink = Arc::clone(&output);
+        let git = GitController::new()
+            .with_log_sink(move |log: CommandLog| *sink.lock().unwrap() = log.output)
+            .with_timeout(self.setting.timeout_for(self.operation.name()))
+            .with_env(env);
+        workers::run_hook(&git, event, script).map_err(|message| {
+            let message = message.strip_prefix("Failed: ").unwrap_or(&message);
+            let output = output.lock().unwrap();
+            format!("{message}\n{}", output.trim_end())
+                .trim_end()
+                .to_string()
+        })
+    }
+
+    // Starts the workers and returns at once; each handle yields its repo's result
+    pub fn spawn(&self, repos: &[&Repos]) -> Vec<JoinHandle<RepoResult>> {
+        repos
+            .iter()
+            .map(|repo| {
+                let runner = self.clone();
+                let repo = (*repo).clone();
+                thread::spawn(move || runner.run_repo(&repo))
+            })
+            .collect()
+    }
+
+    // Runs every repo to the end, results in the order of `repos`
+    pub fn run(&self, repos: &[&Repos]) -> Vec<RepoResult> {
+        self.spawn(repos)
+            .into_iter()
+            .filter_map(|handle| handle.join().ok())
+            .collect()
+    }
+
+    fn run_repo(&self, repo: &Repos) -> RepoResult {
+        let name = repo.name();
+        let path = repo.path();
+        let started_at = Instant::now();
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let last = RefCell::new((RepoStatus::Pending, String::new()));
+        let report = |status: RepoStatus, message: &str, progress: u16| {
+            *last.borrow_mut() = (status.clone(), message.to_string());
+            (self.on_event)(RunEvent::Status {
+                repo: name.clone(),
+                status,
+                message: message.to_string(),
+                progress,
+            });
+        };
+
+        match self.control.acquire() {
+            None => report(RepoStatus::Cancelled, "Cancelled", 0),
+            Some(_slot) => {
+                report(RepoStatus::Running, "Starting...", 10);
+                self.run_operation(repo, &name, &path, &logs, &report);
+            }
+        }
+
+        let (status, message) = last.into_inner();
+        let logs = std::mem::take(&mut *logs.lock().unwrap());
+        RepoResult {
+            name,
+            path,
+            status,
+            message,
+            duration: started_at.elapsed(),
+            logs,
+        }
+    }
+
+    fn backend(&self, command: &str, name: &str, path: &str, logs: Logs) -> Box<dyn GitBackend> {
+        let git = self.controller(command, name, logs).with_dir(path);
+        select_backend(self.setting.backend, git)
+    }
+
+    // Logs into the repo's logs and events, with the command's timeout and the run's
+    // cancellation and dry run
+    fn controller(&self, command: &str, name: &str, logs: Logs) -> GitController {
+        let on_event = Arc::clone(&self.on_event);
+        let repo = name.to_string();
+        GitController::new()
+            .with_log_sink(move |log: CommandLog| {
+                logs.lock().unwrap().push(log.clone());
+                on_event(RunEvent::Log {
+                    repo: repo.clone(),
+                    log,
+                });
+            })
+            .with_timeout(self.setting.timeout_for(command))
+            .with_cancel_flag(self.control.cancel_flag())
+            .with_dry_run(self.control.is_dry_run())
+            .with_retries(self.setting.retries)
+    }
+
+    fn push_job<'a>(
+        &'a self,
+        repo: &'a Repos,
+        files: Vec<&'a ChangedFile>,
+        allow_protected: bool,
+        allow_other_branch: bool,
+    ) -> workers::PushJob<'a> {
+        workers::PushJob {
+            commit_message: self
+                .setting
+                .comments
+                .get("default")
+                .map_or("update.", String::as_str),
+            files,
+            branch: &repo.branch,
+            protected_branches: if allow_protected {
+                &[]
+            } else {
+                self.setting.protected_branches_for(repo)
+            },
+            allow_other_branch,
+            secret_scanner: self.secret_scanner.as_deref(),
+        }
+    }
+
+    // pre_<command> or post_<command> from hooks:, if the command has hooks. It runs in
+    // the repo directory; clone hooks run where gitp was started while there is none yet,
+    // pull and push hooks are skipped for a missing repo.
+    fn run_hook(
+        &self,
+        stage: &str,
+        command: &str,
+        repo: &Repos,
+        logs: &Logs,
+        result: Option<(&RepoStatus, &str)>,
+    ) -> Result<(), String> {
+        let event = format!("{stage}_{command}");
+        let Some(script) = self.setting.hook_for(repo, &event) else {
+            return Ok(());
+        };
+
+        let path = Path::new(&repo.path()).to_path_buf();
+        if !path.is_dir() && command != "clone" {
+            return Ok(());
+        }
+        let absolute_path = std::env::current_dir()
+            .map(|dir| dir.join(&path))
+            .unwrap_or_else(|_| path.clone());
+        let mut env = vec![
+            ("GITP_COMMAND".to_string(), command.to_string()),
+            ("GITP_REPO_NAME".to_string(), repo.name()),
+            (
+                "GITP_REPO_PATH".to_string(),
+                absolute_path.display().to_string(),
+            ),
+            ("GITP_REPO_REMOTE".to_string(), repo.remote.clone()),
+            ("GITP_REPO_BRANCH".to_string(), repo.branch.clone()),
+        ];
+        if let Some((status, message)) = result {
+            env.push(("GITP_STATUS".to_string(), status.label().to_string()));
+            env.push(("GITP_MESSAGE".to_string(), message.to_string()));
+        }
+
+        let mut git = self
+            .controller(command, &repo.name(), Arc::clone(logs))
+            .with_env(env);
+        if path.is_dir() {
+            git = git.with_dir(&path);
+        }
+        workers::run_hook(&git, &event, script)
+    }
+
+    // The pre_<command> hook, the operation and the post_<command> hook, with a backend
+    // that uses the command's timeout. A failing pre hook skips the operation; the post
+    // hook sees the result and only turns a success into a failure.
+    fn run_phase(
+        &self,
+        command: &str,
+        repo: &Repos,
+        logs: &Logs,
+        report: workers::Report,
+        operation: &dyn Fn(&dyn GitBackend, workers::Report),
+    ) {
+        match self.run_hook("pre", command, repo, logs, None) {
+            Err(_) if self.is_cancelled() => report(RepoStatus::Cancelled, "Cancelled", 100),
+            Err(message) => report(RepoStatus::Failed, &message, 100),
+            Ok(()) => {
+                // The final status is held back until the post hook has run, so nothing
+                // sees the repo as finished before it is
+                let finished = RefCell::new(None);
+                let git = self.backend(command, &repo.name(), &repo.path(), Arc::clone(logs));
+                operation(&*git, &|status, message, progress| {
+                    if status.is_done() {
+                        *finished.borrow_mut() = Some((status, message.to_string()));
+                    } else {
+                        report(status, message, progress);
+                    }
+                });
+
+                let Some((status, message)) = finished.into_inner() else {
+                    return;
+                };
+                if status != RepoStatus::Cancelled {
+                    let result =
+                        self.run_hook("post", command, repo, logs, Some((&status, &message)));
+                    if let (Err(message), RepoStatus::Success) = (result, &status) {
+                        report(RepoStatus::Failed, &message, 100);
+                        return;
+                    }
+                }
+                report(status, &message, 100);
+            }
+        }
+    }
+
+    // Sync runs as a pull phase and a push phase, each with the hooks and timeout of
+    // that command
+    fn run_operation(
+        &self,
+        repo: &Repos,
+        name: &str,
+        path: &str,
+        logs: &Logs,
+        report: workers::Report,
+    ) {
+        let setting = &self.setting;
+        let repo_dir = Path::new(path);
+        let phase = |command: &str,
+                     report: workers::Report,
+                     operation: &dyn Fn(&dyn GitBackend, workers::Report)| {
+            self.run_phase(command, repo, logs, report, operation)
+        };
+        let command = self.operation.name();
+        match &*self.operation {
+            Operation::Clone => phase(command, report, &|git, report| {
+                workers::clone_repo(git, &repo.remote, &repo.branch, &setting.user, report)
+            }),
+            Operation::Fetch => phase(command, report, &|git, report| {
+                workers::fetch_repo(git, repo_dir, report)
+            }),
+            Operation::Pull(strategy) => {
+                let strategy = setting.pull_strategy_for(repo, *strategy);
+                phase(command, report, &|git, report| {
+                    workers::pull_repo(git, repo_dir, strategy, &setting.user, report)
+                })
+            }
+            Operation::Push(options) => {
+                let files = options
+                    .files
+                    .get(name)
+                    .map(|files| files.iter().collect())
+                    .unwrap_or_default();
+                let job = self.push_job(
+                    repo,
+                    files,
+                    options.allow_protected,
+                    options.allow_other_branch,
+                );
+                phase(command, report, &|git, report| {
+                    workers::push_repo(git, repo_dir, &setting.user, &job, report)
+                })
+            }
+            Operation::Sync(options) => {
+                let strategy = setting.pull_strategy_for(repo, options.pull_strategy);
+                let files = options
+                    .files
+                    .get(name)
+                    .map(|files| files.iter().collect())
+                    .unwrap_or_default();
+                let job = self.push_job(
+                    repo,
+                    files,
+                    options.allow_protected,
+                    options.allow_other_branch,
+                );
+                workers::sync_repo(
+                    &|report| {
+                        phase("pull", report, &|git, report| {
+                            workers::pull_repo(git, repo_dir, strategy, &setting.user, report)
+                        })
+                    },
+                    &|report| {
+                        phase("push", report, &|git, report| {
+                            workers::sync_push_repo(git, repo_dir, &setting.user, &job, report)
+                        })
+                    },
+                    report,
+                )
+            }
+            Operation::Config => phase(command, report, &|git, report| {
+                workers::config_repo(git, repo_dir, &setting.user, &setting.config, report)
+            }),
+            Operation::ConfigUser => phase(command, report, &|git, report| {
+                workers::config_user_repo(git, repo_dir, &setting.user, report)
+            }),
+            Operation::Exec(program) => phase(command, report, &|git, report| {
+                workers::exec_repo(git, repo_dir, program, report)
+            }),
+        }
+    }
+}
+
+// What `git status` reports for a repo, through the configured backend.
+// A missing repo has no changes.
+pub fn changed_files(setting: &GitpSetting, repo: &Repos) -> Vec<ChangedFile> {
+    let path = repo.path();
+    if !Path::new(&path).is_dir() {
+        return Vec::new();
+    }
+    select_backend(setting.backend, GitController::new().with_dir(&path)).git_changed_files()
+}
+
+// backend: builtin can only be set when the feature is compiled in (see setting_util::load)
+fn select_backend(backend: Backend, git: GitController) -> Box<dyn GitBackend> {
+    match backend {
+        #[cfg(feature = "builtin-git")]
+        Backend::Builtin => Box::new(crate::builtin_git::BuiltinGit::new(git)),
+        _ => Box::new(git),
+    }
+}
@@ -1,34 +1,213 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::fs::File;
 use std::io::Read;
+use std::time::Duration;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct User {
     pub name: String,
     pub email: String,
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum PullStrategy {
+    #[default]
+    Merge,
+    Rebase,
+    FfOnly,
+    Autostash,
+}
+
+impl PullStrategy {
+    pub fn from_flag(flag: &str) -> Option<PullStrategy> {
+        match flag {
+            "--merge" => Some(PullStrategy::Merge),
+            "--rebase" => Some(PullStrategy::Rebase),
+            "--ff-only" => Some(PullStrategy::FfOnly),
+            "--autostash" => Some(PullStrategy::Autostash),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PullStrategy::Merge => "merge",
+            PullStrategy::Rebase => "rebase",
+            PullStrategy::FfOnly => "ff-only",
+            PullStrategy::Autostash => "autostash",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SecretScan {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    // Extra regexes checked in addition to the built-in secret patterns
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    // Regexes for known false positives, matched against the file path or the line
+    #[serde(default)]
+    pub allowlist: Vec<String>,
+    #[serde(default = "default_max_file_size_kb")]
+    pub max_file_size_kb: u64,
+}
+
+impl Default for SecretScan {
+    fn default() -> SecretScan {
+        SecretScan {
+            enabled: true,
+            patterns: Vec::new(),
+            allowlist: Vec::new(),
+            max_file_size_kb: default_max_file_size_kb(),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_retries() -> u32 {
+    2
+}
+
+fn default_max_file_size_kb() -> u64 {
+    5 * 1024
+}
+
+// Seconds, either one value for every command or per command name
+// with an optional "default" entry
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum Timeout {
+    Seconds(u64),
+    PerCommand(HashMap<String, u64>),
+}
+
+// Which GitBackend the workers use
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    #[default]
+    Process, // The git binary
+    Builtin, // gix for clone, fetch and status (feature builtin-git), git for the rest
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Repos {
     pub enabled: bool,
     pub remote: String,
     pub branch: String,
     pub group: String,
+    #[serde(default)]
+    pub pull_strategy: Option<PullStrategy>,
+    #[serde(default)]
+    pub protected_branches: Option<Vec<String>>,
+    #[serde(default)]
+    pub hooks: Option<Hooks>,
+}
+
+impl Repos {
+    // "git@github.com:kako-jun/gitp.git" -> "gitp"
+    pub fn name(&self) -> String {
+        let last_part = self.remote.rsplit('/').next().unwrap_or("");
+        last_part.trim_end_matches(".git").to_string()
+    }
+
+    // Where the repo is cloned, relative to the settings file: <group>/<name>
+    pub fn path(&self) -> String {
+        format!("{}/{}", self.group, self.name())
+    }
+}
+
+// Shell commands run around clone, pull and push, e.g. `post_pull: npm install`.
+// The repo hooks run in the repo directory once it exists; before_all and after_all
+// run once per command where gitp was started, and can only be set globally.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct Hooks {
+    pub pre_clone: Option<String>,
+    pub post_clone: Option<String>,
+    pub pre_pull: Option<String>,
+    pub post_pull: Option<String>,
+    pub pre_push: Option<String>,
+    pub post_push: Option<String>,
+    pub before_all: Option<String>,
+    pub after_all: Option<String>,
+}
+
+impl Hooks {
+    // `event` is the key in the settings, e.g. "post_pull"
+    pub fn get(&self, event: &str) -> Option<&str> {
+        let hook = match event {
+            "pre_clone" => &self.pre_clone,
+            "post_clone" => &self.post_clone,
+            "pre_pull" => &self.pre_pull,
+            "post_pull" => &self.post_pull,
+            "pre_push" => &self.pre_push,
+            "post_push" => &self.post_push,
+            "before_all" => &self.before_all,
+            "after_all" => &self.after_all,
+            _ => &None,
+        };
+        hook.as_deref()
+    }
+}
+
+// A command from commands:, e.g. `up: [pull, push --yes]` or `check: exec cargo check`.
+// Each step is a built-in command line, run one after the other.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum CustomCommand {
+    Step(String),
+    Steps(Vec<String>),
+}
+
+impl CustomCommand {
+    pub fn steps(&self) -> &[String] {
+        match self {
+            CustomCommand::Step(step) => std::slice::from_ref(step),
+            CustomCommand::Steps(steps) => steps,
+        }
+    }
+
+    // "pull → push --yes", for the help text
+    pub fn describe(&self) -> String {
+        self.steps().join(" → ")
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct GitpSetting {
     pub user: User,
     pub comments: HashMap<String, String>,
     #[serde(default)]
     pub config: HashMap<String, String>,
+    #[serde(default)]
+    pub pull_strategy: Option<PullStrategy>,
+    #[serde(default)]
+    pub secret_scan: SecretScan,
+    #[serde(default)]
+    pub protected_branches: Vec<String>,
+    // How often clone, pull and push are retried after a network failure
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    #[serde(default)]
+    pub timeout: Option<Timeout>,
+    #[serde(default)]
+    pub backend: Backend,
+    #[serde(default)]
+    pub commands: BTreeMap<String, CustomCommand>,
+    #[serde(default)]
+    pub hooks: Hooks,
     pub repos: Vec<Repos>,
 }
 
-impl GitpSetting {
-    pub fn default() -> GitpSetting {
+impl Default for GitpSetting {
+    fn default() -> GitpSetting {
         GitpSetting {
             user: User {
                 name: String::from(""),
@@ -36,11 +215,76 @@ impl GitpSetting {
             },
             comments: HashMap::new(),
             config: HashMap::new(),
+            pull_strategy: None,
+            secret_scan: SecretScan::default(),
+            protected_branches: Vec::new(),
+            retries: default_retries(),
+            timeout: None,
+            backend: Backend::default(),
+            commands: BTreeMap::new(),
+            hooks: Hooks::default(),
             repos: Vec::new(),
         }
     }
 }
 
+impl GitpSetting {
+    // In the order of the settings file
+    pub fn enabled_repos(&self) -> Vec<&Repos> {
+        self.repos.iter().filter(|r| r.enabled).collect()
+    }
+
+    // The enabled repos with one of these names
+    pub fn select_repos(&self, names: &[String]) -> Vec<&Repos> {
+        self.repos
+            .iter()
+            .filter(|r| r.enabled && names.contains(&r.name()))
+            .collect()
+    }
+
+    // Priority: command line flag > repo setting > global setting > merge
+    pub fn pull_strategy_for(&self, repo: &Repos, flag: Option<PullStrategy>) -> PullStrategy {
+        flag.or(repo.pull_strategy)
+            .or(self.pull_strategy)
+            .unwrap_or_default()
+    }
+
+    pub fn timeout_for(&self, command: &str) -> Option<Duration> {
+        let seconds = match self.timeout.as_ref()? {
+            Timeout::Seconds(seconds) => *seconds,
+            Timeout::PerCommand(timeouts) => {
+                *timeouts.get(command).or_else(|| timeouts.get("default"))?
+            }
+        };
+        Some(Duration::from_secs(seconds))
+    }
+
+    // A repo's own list replaces the global one
+    pub fn protected_branches_for<'a>(&'a self, repo: &'a Repos) -> &'a [String] {
+        repo.protected_branches
+            .as_deref()
+            .unwrap_or(&self.protected_branches)
+    }
+
+    // A repo's own hook replaces the global one for the same event
+    pub fn hook_for<'a>(&'a self, repo: &'a Repos, event: &str) -> Option<&'a str> {
+        repo.hooks
+            .as_ref()
+            .and_then(|hooks| hooks.get(event))
+            .or_else(|| self.hooks.get(event))
+    }
+}
+
+// "release/*" matches by prefix, anything else must match exactly
+pub fn matches_branch(patterns: &[String], branch: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => branch.starts_with(prefix),
+            None => branch == pattern,
+        })
+}
+
 // pub fn load(mut gitp_setting: GitpSetting) -> Result<GitpSetting, Box<dyn Error>> {
 pub fn load() -> Result<GitpSetting, Box<dyn Error>> {
     // Try .yaml first, then .yml (same logic as gitp.sh)
@@ -56,11 +300,24 @@ pub fn load() -> Result<GitpSetting, Box<dyn Error>> {
     let mut yaml_text = String::new();
     file.read_to_string(&mut yaml_text)?;
 
-    // gitp_setting = serde_yaml::from_str(&yaml_text)?;
+    // A broken file is reported rather than silently treated as "no repositories"
     let mut gitp_setting = GitpSetting::default();
     if !yaml_text.is_empty() {
-        if let Ok(new_gitp_setting) = serde_yaml::from_str(&yaml_text) {
-            gitp_setting = new_gitp_setting;
+        gitp_setting = serde_yaml::from_str(&yaml_text)
+            .map_err(|e| format!("Invalid settings in gitp_setting.yaml: {e}"))?;
+    }
+    if gitp_setting.backend == Backend::Builtin && !cfg!(feature = "builtin-git") {
+        return Err("backend: builtin needs gitp built with --features builtin-git".into());
+    }
+    for repo in &gitp_setting.repos {
+        if let Some(hooks) = &repo.hooks {
+            if hooks.before_all.is_some() || hooks.after_all.is_some() {
+                return Err(format!(
+                    "repos: {}: before_all and after_all can only be set in the global hooks",
+                    repo.name()
+                )
+                .into());
+            }
         }
     }
 
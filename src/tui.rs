@@ -3,25 +3,20 @@ use crossterm::{
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use gitp::git_controller::CommandLog;
+pub use gitp::runner::RepoStatus;
+use gitp::runner::RunEvent;
 use ratatui::{
     backend::{Backend, CrosstermBackend},
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Paragraph},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
     Frame, Terminal,
 };
 use std::io;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
-
-#[derive(Debug, Clone, PartialEq)]
-pub enum RepoStatus {
-    Pending,
-    Running,
-    Success,
-    Failed,
-}
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone)]
 pub struct RepoProgress {
@@ -29,34 +24,123 @@ pub struct RepoProgress {
     pub status: RepoStatus,
     pub message: String,
     pub progress: u16, // 0-100
+    pub logs: Vec<CommandLog>,
+    pub started_at: Option<Instant>,
+    pub duration: Option<Duration>,
+}
+
+impl RepoProgress {
+    // The most telling line of the last failed command, for the summary screen
+    pub fn first_error_line(&self) -> Option<String> {
+        let failed_log = self
+            .logs
+            .iter()
+            .rev()
+            .find(|log| log.exit_code != Some(0))?;
+        let lines: Vec<&str> = failed_log
+            .output
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .collect();
+        lines
+            .iter()
+            .find(|l| l.starts_with("fatal") || l.starts_with("error"))
+            .or(lines.first())
+            .map(|l| l.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Status,
+    Name,
+    Duration,
 }
 
+impl SortOrder {
+    fn next(self) -> SortOrder {
+        match self {
+            SortOrder::Status => SortOrder::Name,
+            SortOrder::Name => SortOrder::Duration,
+            SortOrder::Duration => SortOrder::Status,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SortOrder::Status => "status",
+            SortOrder::Name => "name",
+            SortOrder::Duration => "duration",
+        }
+    }
+}
+
+// Repo sets larger than this start in the one-line-per-repo layout
+const COMPACT_THRESHOLD: usize = 20;
+
 pub struct TuiApp {
     repos: Arc<Mutex<Vec<RepoProgress>>>,
+    list_state: ListState,
+    page_size: usize, // Repos visible in the list, updated on every draw
+    compact: bool,
+    log_view: Option<usize>, // Index of the repo whose log pane is open
+    log_scroll: u16,
+    started_at: Instant,
+    finished_in: Option<Duration>, // Set once every repo is done; switches to the summary
+    sort_order: SortOrder,
+    retry_handler: Option<Box<dyn FnMut(Vec<String>)>>,
+    cancel_handler: Option<Box<dyn FnMut()>>,
+    cancelling: bool, // q was pressed while repos were running; a second q force-quits
 }
 
 impl TuiApp {
     pub fn new(repo_names: Vec<String>) -> Self {
-        let repos = repo_names
+        let repos: Vec<RepoProgress> = repo_names
             .into_iter()
             .map(|name| RepoProgress {
                 name,
                 status: RepoStatus::Pending,
                 message: "Waiting...".to_string(),
                 progress: 0,
+                logs: Vec::new(),
+                started_at: None,
+                duration: None,
             })
             .collect();
 
+        let compact = repos.len() > COMPACT_THRESHOLD;
         TuiApp {
             repos: Arc::new(Mutex::new(repos)),
+            list_state: ListState::default().with_selected(Some(0)),
+            page_size: 1,
+            compact,
+            log_view: None,
+            log_scroll: 0,
+            started_at: Instant::now(),
+            finished_in: None,
+            sort_order: SortOrder::Status,
+            retry_handler: None,
+            cancel_handler: None,
+            cancelling: false,
         }
     }
 
+    // Called with the names of the repos to run again; they are reset to Pending first
+    pub fn set_retry_handler(&mut self, handler: impl FnMut(Vec<String>) + 'static) {
+        self.retry_handler = Some(Box::new(handler));
+    }
+
+    // Called when q is pressed while repos are still running
+    pub fn set_cancel_handler(&mut self, handler: impl FnMut() + 'static) {
+        self.cancel_handler = Some(Box::new(handler));
+    }
+
     pub fn get_repos_handle(&self) -> Arc<Mutex<Vec<RepoProgress>>> {
         Arc::clone(&self.repos)
     }
 
-    pub fn run(&mut self, is_parallel: bool) -> Result<(), io::Error> {
+    pub fn run(&mut self) -> Result<(), io::Error> {
         // Setup terminal
         enable_raw_mode()?;
         let mut stdout = io::stdout();
@@ -65,7 +149,8 @@ impl TuiApp {
         let mut terminal = Terminal::new(backend)?;
 
         // Run the app
-        let res = self.run_app(&mut terminal, is_parallel);
+        self.started_at = Instant::now();
+        let res = self.run_app(&mut terminal);
 
         // Restore terminal
         disable_raw_mode()?;
@@ -83,33 +168,45 @@ impl TuiApp {
         Ok(())
     }
 
-    fn run_app<B: Backend>(
-        &mut self,
-        terminal: &mut Terminal<B>,
-        _is_parallel: bool,
-    ) -> io::Result<()> {
+    fn run_app<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> io::Result<()> {
         loop {
             terminal.draw(|f| self.ui(f))?;
 
-            // Check if all repos are done
+            // Check if all repos are done, then stay on the summary until the user leaves
             let repos = self.repos.lock().unwrap();
-            let all_done = repos
-                .iter()
-                .all(|r| r.status == RepoStatus::Success || r.status == RepoStatus::Failed);
+            let all_done = repos.iter().all(|r| r.status.is_done());
             drop(repos);
 
-            if all_done {
-                // Show final state for 1 second
-                std::thread::sleep(Duration::from_secs(1));
-                break;
+            if all_done && self.finished_in.is_none() {
+                self.finished_in = Some(self.started_at.elapsed());
+                self.cancelling = false;
+                self.list_state.select(Some(0));
             }
 
             // Poll for events (non-blocking with timeout)
             if event::poll(Duration::from_millis(100))? {
                 if let Event::Key(key) = event::read()? {
-                    if let KeyCode::Char('q') = key.code {
+                    if key.code == KeyCode::Char('q') {
+                        // The first q while running cancels the run, the next one leaves
+                        if self.finished_in.is_some() || self.cancelling {
+                            break;
+                        }
+                        match self.cancel_handler.as_mut() {
+                            Some(handler) => {
+                                handler();
+                                self.cancelling = true;
+                            }
+                            None => break,
+                        }
+                        continue;
+                    }
+                    if key.code == KeyCode::Esc
+                        && self.finished_in.is_some()
+                        && self.log_view.is_none()
+                    {
                         break;
                     }
+                    self.handle_key(key.code);
                 }
             }
         }
@@ -117,7 +214,131 @@ impl TuiApp {
         Ok(())
     }
 
-    fn ui(&self, f: &mut Frame) {
+    fn handle_key(&mut self, code: KeyCode) {
+        let repo_count = self.repos.lock().unwrap().len();
+
+        if self.log_view.is_some() {
+            match code {
+                KeyCode::Esc | KeyCode::Enter => self.log_view = None,
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.log_scroll = self.log_scroll.saturating_sub(1)
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.log_scroll = self.log_scroll.saturating_add(1)
+                }
+                KeyCode::PageUp => self.log_scroll = self.log_scroll.saturating_sub(10),
+                KeyCode::PageDown => self.log_scroll = self.log_scroll.saturating_add(10),
+                KeyCode::Home => self.log_scroll = 0,
+                _ => {}
+            }
+            return;
+        }
+
+        let page = self.page_size.max(1) as isize;
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1, repo_count),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1, repo_count),
+            KeyCode::PageUp => self.move_selection(-page, repo_count),
+            KeyCode::PageDown => self.move_selection(page, repo_count),
+            KeyCode::Home => self.list_state.select(Some(0)),
+            KeyCode::End => self.list_state.select(Some(repo_count.saturating_sub(1))),
+            KeyCode::Char('c') => {
+                self.compact = !self.compact;
+                // Let the list scroll back so the selection stays in view
+                *self.list_state.offset_mut() = 0;
+            }
+            KeyCode::Char('s') if self.finished_in.is_some() => {
+                self.sort_order = self.sort_order.next();
+            }
+            KeyCode::Char('r') if self.finished_in.is_some() => {
+                let repos = self.repos.lock().unwrap();
+                let failed = repos
+                    .iter()
+                    .filter(|r| matches!(r.status, RepoStatus::Failed | RepoStatus::Cancelled))
+                    .map(|r| r.name.clone())
+                    .collect();
+                drop(repos);
+                self.retry(failed);
+            }
+            KeyCode::Char('R') if self.finished_in.is_some() => {
+                let repos = self.repos.lock().unwrap();
+                let order = self.display_order(&repos);
+                let selected = self
+                    .list_state
+                    .selected()
+                    .and_then(|i| order.get(i))
+                    .map(|&i| &repos[i])
+                    .filter(|r| r.status.is_done())
+                    .map(|r| vec![r.name.clone()])
+                    .unwrap_or_default();
+                drop(repos);
+                self.retry(selected);
+            }
+            KeyCode::Enter => {
+                let repos = self.repos.lock().unwrap();
+                let order = self.display_order(&repos);
+                drop(repos);
+                self.log_view = self
+                    .list_state
+                    .selected()
+                    .and_then(|i| order.get(i).copied());
+                self.log_scroll = 0;
+            }
+            _ => {}
+        }
+    }
+
+    fn retry(&mut self, names: Vec<String>) {
+        if names.is_empty() {
+            return;
+        }
+        let Some(handler) = self.retry_handler.as_mut() else {
+            return;
+        };
+
+        let mut repos = self.repos.lock().unwrap();
+        for repo in repos.iter_mut().filter(|r| names.contains(&r.name)) {
+            repo.status = RepoStatus::Pending;
+            repo.message = "Waiting for retry...".to_string();
+            repo.progress = 0;
+            repo.logs.clear();
+            repo.started_at = None;
+            repo.duration = None;
+        }
+        drop(repos);
+
+        // Back to the progress view until the retried repos are done
+        self.finished_in = None;
+        handler(names);
+    }
+
+    // Indices into the repo list in the order they are shown. The run keeps
+    // the configured order; the summary sorts by the chosen key.
+    fn display_order(&self, repos: &[RepoProgress]) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..repos.len()).collect();
+        if self.finished_in.is_none() {
+            return order;
+        }
+        match self.sort_order {
+            SortOrder::Status => order.sort_by_key(|&i| status_rank(&repos[i].status)),
+            SortOrder::Name => order.sort_by(|&a, &b| repos[a].name.cmp(&repos[b].name)),
+            SortOrder::Duration => {
+                order.sort_by(|&a, &b| repos[b].duration.cmp(&repos[a].duration))
+            }
+        }
+        order
+    }
+
+    fn move_selection(&mut self, delta: isize, repo_count: usize) {
+        if repo_count == 0 {
+            return;
+        }
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        let next = (current + delta).clamp(0, repo_count as isize - 1);
+        self.list_state.select(Some(next as usize));
+    }
+
+    fn ui(&mut self, f: &mut Frame) {
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .margin(2)
@@ -145,16 +366,24 @@ impl TuiApp {
         );
         f.render_widget(header, chunks[0]);
 
-        // Repository list
-        self.render_repos(f, chunks[1]);
+        // Repository list, or the log pane of the selected repo
+        match (self.log_view, self.finished_in) {
+            (Some(index), _) => self.render_log(f, chunks[1], index),
+            (None, Some(elapsed)) => {
+                let summary_chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Length(3), Constraint::Min(0)])
+                    .split(chunks[1]);
+                self.render_summary(f, summary_chunks[0], elapsed);
+                self.render_repos(f, summary_chunks[1]);
+            }
+            (None, None) => self.render_repos(f, chunks[1]),
+        }
 
         // Footer
         let repos = self.repos.lock().unwrap();
         let total = repos.len();
-        let completed = repos
-            .iter()
-            .filter(|r| r.status == RepoStatus::Success || r.status == RepoStatus::Failed)
-            .count();
+        let completed = repos.iter().filter(|r| r.status.is_done()).count();
         let success = repos
             .iter()
             .filter(|r| r.status == RepoStatus::Success)
@@ -163,6 +392,14 @@ impl TuiApp {
             .iter()
             .filter(|r| r.status == RepoStatus::Failed)
             .count();
+        let conflict = repos
+            .iter()
+            .filter(|r| r.status == RepoStatus::Conflict)
+            .count();
+        let cancelled = repos
+            .iter()
+            .filter(|r| r.status == RepoStatus::Cancelled)
+            .count();
         drop(repos);
 
         let footer = Paragraph::new(Line::from(vec![
@@ -178,8 +415,22 @@ impl TuiApp {
             Span::styled("Failed: ", Style::default().fg(Color::White)),
             Span::styled(format!("{failed} "), Style::default().fg(Color::Red)),
             Span::raw("| "),
+            Span::styled("Conflict: ", Style::default().fg(Color::White)),
+            Span::styled(format!("{conflict} "), Style::default().fg(Color::Magenta)),
+            Span::raw("| "),
+            Span::styled("Cancelled: ", Style::default().fg(Color::White)),
+            Span::styled(format!("{cancelled} "), Style::default().fg(Color::Gray)),
+            Span::raw("| "),
             Span::styled(
-                "Press 'q' to force quit",
+                if self.cancelling {
+                    "Cancelling... press q again to force quit"
+                } else if self.log_view.is_some() {
+                    "↑↓ scroll, Esc close log, q quit"
+                } else if self.finished_in.is_some() {
+                    "↑↓ select, Enter log, s sort, r/R retry failed/selected, q close"
+                } else {
+                    "↑↓ select, Enter log, c compact, q quit"
+                },
                 Style::default().fg(Color::DarkGray),
             ),
         ]))
@@ -187,19 +438,22 @@ impl TuiApp {
         f.render_widget(footer, chunks[2]);
     }
 
-    fn render_repos(&self, f: &mut Frame, area: Rect) {
+    fn render_repos(&mut self, f: &mut Frame, area: Rect) {
         let repos = self.repos.lock().unwrap();
+        let order = self.display_order(&repos);
 
-        let mut lines = vec![];
-        for repo in repos.iter() {
+        let mut items = vec![];
+        for repo in order.iter().map(|&i| &repos[i]) {
             let (status_icon, status_color) = match repo.status {
                 RepoStatus::Pending => ("⏸", Color::DarkGray),
                 RepoStatus::Running => ("⚙", Color::Yellow),
                 RepoStatus::Success => ("✓", Color::Green),
                 RepoStatus::Failed => ("✗", Color::Red),
+                RepoStatus::Conflict => ("⚠", Color::Magenta),
+                RepoStatus::Cancelled => ("⊘", Color::Gray),
             };
 
-            lines.push(Line::from(vec![
+            let mut name_line = vec![
                 Span::styled(
                     format!(" {status_icon} "),
                     Style::default()
@@ -212,11 +466,48 @@ impl TuiApp {
                         .fg(Color::Cyan)
                         .add_modifier(Modifier::BOLD),
                 ),
-                Span::styled(
-                    format!(" {}", repo.message),
+            ];
+
+            if self.finished_in.is_some() {
+                let duration = repo.duration.map(format_duration).unwrap_or_default();
+                name_line.push(Span::styled(
+                    format!(" {duration:>8} "),
+                    Style::default().fg(Color::DarkGray),
+                ));
+                name_line.push(Span::styled(
+                    repo.message.clone(),
                     Style::default().fg(Color::White),
-                ),
-            ]));
+                ));
+                let mut lines = vec![Line::from(name_line)];
+                if matches!(repo.status, RepoStatus::Failed | RepoStatus::Conflict) {
+                    if let Some(error) = repo.first_error_line() {
+                        lines.push(Line::from(Span::styled(
+                            format!("     {error}"),
+                            Style::default().fg(status_color),
+                        )));
+                    }
+                }
+                items.push(ListItem::new(lines));
+                continue;
+            }
+
+            if self.compact {
+                name_line.push(Span::styled(
+                    format!(" {:>3}% ", repo.progress),
+                    Style::default().fg(status_color),
+                ));
+                name_line.push(Span::styled(
+                    repo.message.clone(),
+                    Style::default().fg(Color::White),
+                ));
+                items.push(ListItem::new(Line::from(name_line)));
+                continue;
+            }
+
+            name_line.push(Span::styled(
+                format!(" {}", repo.message),
+                Style::default().fg(Color::White),
+            ));
 
             // Progress bar line
             let bar_width = 50;
@@ -229,26 +520,110 @@ impl TuiApp {
                 repo.progress
             );
 
+            items.push(ListItem::new(vec![
+                Line::from(name_line),
+                Line::from(Span::styled(bar, Style::default().fg(status_color))),
+                Line::from(""), // Empty line between repos
+            ]));
+        }
+        drop(repos);
+
+        let lines_per_repo = if self.compact { 1 } else { 3 };
+        self.page_size = (area.height.saturating_sub(2) as usize / lines_per_repo).max(1);
+
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(" Repositories ")
+                    .style(Style::default()),
+            )
+            .style(Style::default().bg(Color::Black))
+            .highlight_style(Style::default().bg(Color::DarkGray));
+
+        f.render_stateful_widget(list, area, &mut self.list_state);
+    }
+
+    fn render_summary(&self, f: &mut Frame, area: Rect, elapsed: Duration) {
+        let repos = self.repos.lock().unwrap();
+        let count = |status: RepoStatus| repos.iter().filter(|r| r.status == status).count();
+
+        let summary = Paragraph::new(Line::from(vec![
+            Span::styled(
+                format!("Finished in {} ", format_duration(elapsed)),
+                Style::default()
+                    .fg(Color::White)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::raw("| "),
+            Span::styled(
+                format!("✓ {} ", count(RepoStatus::Success)),
+                Style::default().fg(Color::Green),
+            ),
+            Span::styled(
+                format!("✗ {} ", count(RepoStatus::Failed)),
+                Style::default().fg(Color::Red),
+            ),
+            Span::styled(
+                format!("⚠ {} ", count(RepoStatus::Conflict)),
+                Style::default().fg(Color::Magenta),
+            ),
+            Span::styled(
+                format!("⊘ {} ", count(RepoStatus::Cancelled)),
+                Style::default().fg(Color::Gray),
+            ),
+            Span::raw("| "),
+            Span::styled(
+                format!("Sorted by {}", self.sort_order.label()),
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]))
+        .block(Block::default().borders(Borders::ALL).title(" Summary "));
+
+        f.render_widget(summary, area);
+    }
+
+    fn render_log(&self, f: &mut Frame, area: Rect, index: usize) {
+        let repos = self.repos.lock().unwrap();
+        let Some(repo) = repos.get(index) else {
+            return;
+        };
+
+        let mut lines = vec![];
+        for log in &repo.logs {
+            let (exit, exit_color) = match log.exit_code {
+                Some(0) => ("exit 0".to_string(), Color::Green),
+                Some(code) => (format!("exit {code}"), Color::Red),
+                None => ("killed".to_string(), Color::Red),
+            };
+            lines.push(Line::from(vec![
+                Span::styled(
+                    format!("$ {} ", log.command),
+                    Style::default()
+                        .fg(Color::Cyan)
+                        .add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(format!("[{exit}]"), Style::default().fg(exit_color)),
+            ]));
+            for line in log.output.lines() {
+                lines.push(Line::from(format!("  {line}")));
+            }
+        }
+        if lines.is_empty() {
             lines.push(Line::from(Span::styled(
-                bar,
-                Style::default().fg(match repo.status {
-                    RepoStatus::Success => Color::Green,
-                    RepoStatus::Failed => Color::Red,
-                    RepoStatus::Running => Color::Yellow,
-                    RepoStatus::Pending => Color::DarkGray,
-                }),
+                "No commands have run yet.",
+                Style::default().fg(Color::DarkGray),
             )));
-
-            lines.push(Line::from("")); // Empty line between repos
         }
 
         let paragraph = Paragraph::new(lines)
             .block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .title(" Repositories ")
-                    .style(Style::default()),
+                    .title(format!(" Log: {} ", repo.name)),
             )
+            .wrap(Wrap { trim: false })
+            .scroll((self.log_scroll, 0))
             .style(Style::default().bg(Color::Black));
 
         f.render_widget(paragraph, area);
@@ -264,8 +639,54 @@ pub fn update_repo_status(
 ) {
     let mut repos = repos.lock().unwrap();
     if let Some(repo) = repos.iter_mut().find(|r| r.name == repo_name) {
+        if repo.started_at.is_none() && status != RepoStatus::Pending {
+            repo.started_at = Some(Instant::now());
+        }
+        if status.is_done() {
+            repo.duration = repo.started_at.map(|t| t.elapsed());
+        }
         repo.status = status;
         repo.message = message.to_string();
         repo.progress = progress;
     }
 }
+
+fn status_rank(status: &RepoStatus) -> u8 {
+    match status {
+        RepoStatus::Failed => 0,
+        RepoStatus::Conflict => 1,
+        RepoStatus::Cancelled => 2,
+        RepoStatus::Running => 3,
+        RepoStatus::Pending => 4,
+        RepoStatus::Success => 5,
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs_f32();
+    if secs < 60.0 {
+        format!("{secs:.1}s")
+    } else {
+        format!("{}m {:02}s", secs as u64 / 60, secs as u64 % 60)
+    }
+}
+
+// Where the events of a Runner or a plugin end up
+pub fn apply_run_event(repos: &Arc<Mutex<Vec<RepoProgress>>>, event: RunEvent) {
+    match event {
+        RunEvent::Status {
+            repo,
+            status,
+            message,
+            progress,
+        } => update_repo_status(repos, &repo, status, &message, progress),
+        RunEvent::Log { repo, log } => append_repo_log(repos, &repo, log),
+    }
+}
+
+pub fn append_repo_log(repos: &Arc<Mutex<Vec<RepoProgress>>>, repo_name: &str, log: CommandLog) {
+    let mut repos = repos.lock().unwrap();
+    if let Some(repo) = repos.iter_mut().find(|r| r.name == repo_name) {
+        repo.logs.push(log);
+    }
+}
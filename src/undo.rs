@@ -0,0 +1,113 @@
+use crate::run_history::{HeadState, RepoRecord, RunRecord};
+use gitp::git_controller::{GitBackend, GitController};
+use serde::Serialize;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UndoOutcome {
+    Reset,     // Moved back to the HEAD recorded before the run
+    Unchanged, // The run did not move HEAD, nothing to undo
+    Refused,   // Changed since the run, or cannot be undone safely
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UndoResult {
+    pub name: String,
+    pub path: String,
+    pub outcome: UndoOutcome,
+    pub message: String,
+    pub warning: Option<String>,
+}
+
+// Resets every repo of the run to its recorded HEAD. `pushed` is for runs that push
+// the commits they make (push, sync), to warn when those commits are on a remote.
+pub fn undo_run(record: &RunRecord, pushed: bool) -> Vec<UndoResult> {
+    record
+        .repos
+        .iter()
+        .map(|repo| {
+            let (outcome, message, warning) = undo_repo(repo, pushed);
+            UndoResult {
+                name: repo.name.clone(),
+                path: repo.path.clone(),
+                outcome,
+                message,
+                warning,
+            }
+        })
+        .collect()
+}
+
+fn undo_repo(repo: &RepoRecord, pushed: bool) -> (UndoOutcome, String, Option<String>) {
+    let refused = |message: &str| (UndoOutcome::Refused, message.to_string(), None);
+
+    let (before, after) = match (&repo.head_before, &repo.head_after) {
+        (None, _) => {
+            return (
+                UndoOutcome::Unchanged,
+                "Did not exist before the run".to_string(),
+                None,
+            )
+        }
+        (Some(_), None) => return refused("Refused: HEAD after the run was not recorded"),
+        (Some(before), Some(after)) => (before, after),
+    };
+    if before == after {
+        return (
+            UndoOutcome::Unchanged,
+            "HEAD did not move in this run".to_string(),
+            None,
+        );
+    }
+    if before.branch != after.branch {
+        return refused("Refused: the run switched branches");
+    }
+
+    if !Path::new(&repo.path).join(".git").exists() {
+        return refused("Refused: repository not found");
+    }
+    let git = GitController::new().with_dir(&repo.path);
+    let current = git.git_head().map(|commit| HeadState {
+        commit,
+        branch: git.git_current_branch(),
+    });
+    match current {
+        Some(current) if current == *after => {}
+        Some(current) if current == *before => {
+            return (
+                UndoOutcome::Unchanged,
+                "Already at the HEAD from before the run".to_string(),
+                None,
+            )
+        }
+        _ => return refused("Refused: changed since the run"),
+    }
+
+    // Undoing does not take commits back from a remote. After a pull they came from
+    // there in the first place, so that is not worth a warning.
+    let remotes = if pushed {
+        git.git_remote_branches_containing(&after.commit)
+    } else {
+        Vec::new()
+    };
+    let warning = (!remotes.is_empty()).then(|| {
+        format!(
+            "Commits are already on {}; only the local branch was reset",
+            remotes.join(", ")
+        )
+    });
+
+    let result = git.git_reset_keep(&before.commit);
+    if result.contains("fatal") || result.contains("error") {
+        let reason = result.lines().next().unwrap_or("").trim();
+        return (UndoOutcome::Refused, format!("Failed: {reason}"), None);
+    }
+
+    let short = &before.commit[..before.commit.len().min(7)];
+    (
+        UndoOutcome::Reset,
+        format!("Reset to {short} (previous HEAD kept in the reflog)"),
+        warning,
+    )
+}
@@ -0,0 +1,657 @@
+// Runs the gitp binary against bare repositories in a temporary directory
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+struct Sandbox {
+    dir: TempDir,
+}
+
+impl Sandbox {
+    // One bare remote per name, each with an initial commit on main
+    fn new(repos: &[&str]) -> Self {
+        Sandbox::with_settings(repos, "")
+    }
+
+    // `extra` is added to gitp_setting.yaml before the repos
+    fn with_settings(repos: &[&str], extra: &str) -> Self {
+        let sandbox = Sandbox {
+            dir: tempfile::tempdir().unwrap(),
+        };
+        let mut setting = format!(
+            "user:\n  name: tester\n  email: tester@example.com\ncomments:\n  default: update.\n{extra}repos:\n"
+        );
+        for name in repos {
+            let remote = sandbox.remote(name);
+            git(
+                sandbox.path(),
+                &["init", "--bare", "-b", "main", path_str(&remote)],
+            );
+            sandbox.commit_to_remote(name, "README.md", "initial\n");
+            setting.push_str(&format!(
+                "  - enabled: true\n    remote: {}\n    branch: main\n    group: ws\n",
+                remote.display()
+            ));
+        }
+        fs::write(sandbox.path().join("gitp_setting.yaml"), setting).unwrap();
+        sandbox
+    }
+
+    fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    fn remote(&self, name: &str) -> PathBuf {
+        self.path().join("remotes").join(format!("{name}.git"))
+    }
+
+    fn workspace(&self, name: &str) -> PathBuf {
+        self.path().join("ws").join(name)
+    }
+
+    // Pushes a commit to the remote from a scratch clone, as another user would
+    fn commit_to_remote(&self, name: &str, file: &str, content: &str) {
+        let scratch = self.path().join("scratch").join(name);
+        if !scratch.exists() {
+            git(
+                self.path(),
+                &["clone", path_str(&self.remote(name)), path_str(&scratch)],
+            );
+            git(&scratch, &["checkout", "-B", "main"]);
+        }
+        fs::write(scratch.join(file), content).unwrap();
+        git(&scratch, &["add", file]);
+        git(&scratch, &["commit", "-m", &format!("edit {file}")]);
+        git(&scratch, &["push", "origin", "main"]);
+    }
+
+    // An executable script in a directory that gitp finds first on PATH
+    #[cfg(unix)]
+    fn add_plugin(&self, name: &str, script: &str) {
+        use std::os::unix::fs::PermissionsExt;
+        let path = self.path().join("bin").join(format!("gitp-{name}"));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, format!("#!/bin/sh\n{script}")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    fn gitp(&self, args: &[&str]) -> Output {
+        let mut paths = vec![self.path().join("bin")];
+        paths.extend(std::env::split_paths(&std::env::var_os("PATH").unwrap()));
+        Command::new(env!("CARGO_BIN_EXE_gitp"))
+            .args(args)
+            .current_dir(self.path())
+            .env("XDG_STATE_HOME", self.path().join("state"))
+            .env("HOME", self.path())
+            .env("PATH", std::env::join_paths(paths).unwrap())
+            .output()
+            .unwrap()
+    }
+}
+
+fn path_str(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .env("GIT_AUTHOR_NAME", "seed")
+        .env("GIT_AUTHOR_EMAIL", "seed@example.com")
+        .env("GIT_COMMITTER_NAME", "seed")
+        .env("GIT_COMMITTER_EMAIL", "seed@example.com")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "git {args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn json_report(output: &Output) -> serde_json::Value {
+    serde_json::from_slice(&output.stdout).unwrap_or_else(|e| {
+        panic!(
+            "not a JSON report ({e}): {}",
+            String::from_utf8_lossy(&output.stdout)
+        )
+    })
+}
+
+#[test]
+fn clone_checks_out_every_repo() {
+    let sandbox = Sandbox::new(&["alpha", "beta"]);
+
+    let output = sandbox.gitp(&["clone", "--json"]);
+    assert_eq!(output.status.code(), Some(0));
+
+    let report = json_report(&output);
+    let statuses: Vec<&str> = report["repos"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|repo| repo["status"].as_str().unwrap())
+        .collect();
+    assert_eq!(statuses, ["success", "success"]);
+
+    for name in ["alpha", "beta"] {
+        let repo = sandbox.workspace(name);
+        assert!(repo.join("README.md").exists());
+        assert_eq!(git(&repo, &["config", "user.name"]).trim(), "tester");
+    }
+}
+
+#[test]
+fn clone_of_a_missing_remote_fails() {
+    let sandbox = Sandbox::new(&["alpha"]);
+    fs::remove_dir_all(sandbox.remote("alpha")).unwrap();
+
+    let output = sandbox.gitp(&["clone", "--no-tui"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("failed"));
+}
+
+#[test]
+fn pull_brings_in_remote_commits() {
+    let sandbox = Sandbox::new(&["alpha"]);
+    assert!(sandbox.gitp(&["clone", "--no-tui"]).status.success());
+
+    sandbox.commit_to_remote("alpha", "NEWS.md", "news\n");
+    let output = sandbox.gitp(&["pull", "--no-tui"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        fs::read_to_string(sandbox.workspace("alpha").join("NEWS.md")).unwrap(),
+        "news\n"
+    );
+}
+
+#[test]
+fn pull_conflict_is_aborted() {
+    let sandbox = Sandbox::new(&["alpha"]);
+    assert!(sandbox.gitp(&["clone", "--no-tui"]).status.success());
+
+    let repo = sandbox.workspace("alpha");
+    fs::write(repo.join("README.md"), "local\n").unwrap();
+    git(&repo, &["commit", "-am", "local edit"]);
+    let local_head = git(&repo, &["rev-parse", "HEAD"]);
+    sandbox.commit_to_remote("alpha", "README.md", "remote\n");
+
+    let output = sandbox.gitp(&["pull", "--json"]);
+    assert_eq!(output.status.code(), Some(2));
+    let report = json_report(&output);
+    assert_eq!(report["repos"][0]["status"], "conflict");
+    // Of the pull, not of the abort and the queries after it
+    assert_eq!(report["repos"][0]["exit_code"], 1);
+    assert_eq!(git(&repo, &["rev-parse", "HEAD"]), local_head);
+    assert!(git(&repo, &["status", "--porcelain"]).is_empty());
+}
+
+#[test]
+fn push_commits_and_pushes_local_changes() {
+    let sandbox = Sandbox::new(&["alpha"]);
+    assert!(sandbox.gitp(&["clone", "--no-tui"]).status.success());
+
+    fs::write(sandbox.workspace("alpha").join("notes.txt"), "hello\n").unwrap();
+    let output = sandbox.gitp(&["push", "--yes", "--no-tui"]);
+    assert_eq!(
+        output.status.code(),
+        Some(0),
+        "{}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let remote = sandbox.remote("alpha");
+    let log = git(&remote, &["log", "--format=%s|%an", "main"]);
+    assert_eq!(log.lines().next(), Some("update.|tester"));
+    assert_eq!(git(&remote, &["show", "main:notes.txt"]), "hello\n");
+}
+
+#[test]
+fn dry_run_push_changes_nothing() {
+    let sandbox = Sandbox::new(&["alpha"]);
+    assert!(sandbox.gitp(&["clone", "--no-tui"]).status.success());
+
+    let remote = sandbox.remote("alpha");
+    let remote_head = git(&remote, &["rev-parse", "main"]);
+    fs::write(sandbox.workspace("alpha").join("notes.txt"), "hello\n").unwrap();
+
+    let output = sandbox.gitp(&["push", "--dry-run"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("git push"));
+    assert_eq!(git(&remote, &["rev-parse", "main"]), remote_head);
+    assert!(git(&sandbox.workspace("alpha"), &["status", "--porcelain"]).contains("notes.txt"));
+}
+
+#[test]
+fn dry_run_exec_runs_nothing() {
+    let sandbox = Sandbox::new(&["alpha"]);
+    assert!(sandbox.gitp(&["clone", "--no-tui"]).status.success());
+
+    let output = sandbox.gitp(&["exec", "--dry-run", "git", "tag", "x"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("git tag x"));
+    assert_eq!(git(&sandbox.workspace("alpha"), &["tag"]), "");
+}
+
+#[test]
+fn fetch_updates_remote_tracking_branches() {
+    let sandbox = Sandbox::new(&["alpha"]);
+    assert!(sandbox.gitp(&["clone", "--no-tui"]).status.success());
+
+    sandbox.commit_to_remote("alpha", "NEWS.md", "news\n");
+    let repo = sandbox.workspace("alpha");
+    let head = git(&repo, &["rev-parse", "HEAD"]);
+
+    let output = sandbox.gitp(&["fetch", "--no-tui"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        git(&repo, &["rev-parse", "origin/main"]),
+        git(&sandbox.remote("alpha"), &["rev-parse", "main"])
+    );
+    assert_eq!(git(&repo, &["rev-parse", "HEAD"]), head);
+}
+
+#[test]
+fn sync_pulls_then_commits_and_pushes() {
+    let sandbox = Sandbox::new(&["alpha"]);
+    assert!(sandbox.gitp(&["clone", "--no-tui"]).status.success());
+
+    sandbox.commit_to_remote("alpha", "NEWS.md", "news\n");
+    fs::write(sandbox.workspace("alpha").join("notes.txt"), "hello\n").unwrap();
+    let remote = sandbox.remote("alpha");
+    let remote_head = git(&remote, &["rev-parse", "main"]);
+
+    // Like push, committing without a review needs --yes
+    let output = sandbox.gitp(&["sync", "--no-tui"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--yes"));
+    assert_eq!(git(&remote, &["rev-parse", "main"]), remote_head);
+
+    let output = sandbox.gitp(&["sync", "--yes", "--no-tui"]);
+    assert_eq!(output.status.code(), Some(0));
+
+    assert_eq!(git(&remote, &["show", "main:notes.txt"]), "hello\n");
+    assert_eq!(git(&remote, &["show", "main:NEWS.md"]), "news\n");
+}
+
+#[test]
+fn sync_does_not_push_after_a_conflict() {
+    let sandbox = Sandbox::new(&["alpha"]);
+    assert!(sandbox.gitp(&["clone", "--no-tui"]).status.success());
+
+    let repo = sandbox.workspace("alpha");
+    fs::write(repo.join("README.md"), "local\n").unwrap();
+    git(&repo, &["commit", "-am", "local edit"]);
+    fs::write(repo.join("notes.txt"), "hello\n").unwrap();
+    sandbox.commit_to_remote("alpha", "README.md", "remote\n");
+    let remote_head = git(&sandbox.remote("alpha"), &["rev-parse", "main"]);
+
+    let output = sandbox.gitp(&["sync", "--yes", "--json"]);
+    assert_eq!(output.status.code(), Some(2));
+    let report = json_report(&output);
+    assert_eq!(report["repos"][0]["status"], "conflict");
+    assert!(report["repos"][0]["message"]
+        .as_str()
+        .unwrap()
+        .ends_with("not pushed"));
+    assert_eq!(
+        git(&sandbox.remote("alpha"), &["rev-parse", "main"]),
+        remote_head
+    );
+    assert!(git(&repo, &["status", "--porcelain"]).contains("notes.txt"));
+}
+
+#[test]
+fn exec_runs_in_every_repo() {
+    let sandbox = Sandbox::new(&["alpha", "beta"]);
+    assert!(sandbox.gitp(&["clone", "--no-tui"]).status.success());
+
+    let output = sandbox.gitp(&["exec", "--no-tui", "git", "tag", "v1"]);
+    assert_eq!(output.status.code(), Some(0));
+    for name in ["alpha", "beta"] {
+        assert_eq!(git(&sandbox.workspace(name), &["tag"]), "v1\n");
+    }
+}
+
+#[test]
+fn custom_command_runs_its_steps_in_order() {
+    let sandbox =
+        Sandbox::with_settings(&["alpha"], "commands:\n  setup: [clone, exec git tag v1]\n");
+
+    let output = sandbox.gitp(&["setup", "--no-tui"]);
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("→ gitp clone --no-tui"));
+    assert!(stdout.contains("→ gitp exec --no-tui git tag v1"));
+    assert_eq!(git(&sandbox.workspace("alpha"), &["tag"]), "v1\n");
+}
+
+#[test]
+fn custom_command_stops_after_a_failed_step() {
+    let sandbox =
+        Sandbox::with_settings(&["alpha"], "commands:\n  up: [pull, exec touch pulled]\n");
+
+    // Nothing is cloned, so the pull fails
+    let output = sandbox.gitp(&["up", "--no-tui"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("→ gitp exec"));
+}
+
+#[test]
+fn custom_command_prints_one_json_document() {
+    let sandbox =
+        Sandbox::with_settings(&["alpha"], "commands:\n  setup: [clone, exec git tag v1]\n");
+
+    let output = sandbox.gitp(&["setup", "--json"]);
+    assert_eq!(output.status.code(), Some(0));
+    let reports = json_report(&output);
+    let commands: Vec<&str> = reports
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|report| report["command"].as_str().unwrap())
+        .collect();
+    assert_eq!(commands, ["clone", "exec"]);
+}
+
+#[test]
+fn custom_command_cannot_hide_a_built_in_one() {
+    let sandbox = Sandbox::with_settings(&["alpha"], "commands:\n  pull: exec ls\n");
+
+    let output = sandbox.gitp(&["help"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("pull is already a built-in"));
+}
+
+#[test]
+fn post_pull_hook_runs_in_the_repo_with_its_result() {
+    let sandbox = Sandbox::with_settings(
+        &["alpha"],
+        // The pull is reported only once the hook is done, however long it takes
+        "hooks:\n  post_pull: sleep 1; echo \"$GITP_REPO_NAME $GITP_STATUS\" > hook.txt\n",
+    );
+    assert!(sandbox.gitp(&["clone", "--no-tui"]).status.success());
+
+    let output = sandbox.gitp(&["pull", "--json"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        fs::read_to_string(sandbox.workspace("alpha").join("hook.txt")).unwrap(),
+        "alpha success\n"
+    );
+    assert!(json_report(&output)["repos"][0]["commands"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|command| command.as_str().unwrap().starts_with("sh -c")));
+}
+
+#[test]
+fn failing_post_pull_hook_fails_the_pull() {
+    let sandbox = Sandbox::with_settings(&["alpha"], "hooks:\n  post_pull: sleep 1; exit 3\n");
+    assert!(sandbox.gitp(&["clone", "--no-tui"]).status.success());
+
+    let output = sandbox.gitp(&["pull", "--json"]);
+    assert_eq!(output.status.code(), Some(2));
+    let report = json_report(&output);
+    assert_eq!(report["repos"][0]["status"], "failed");
+    assert_eq!(
+        report["repos"][0]["message"],
+        "Failed: post_pull hook exited with 3"
+    );
+    assert_eq!(report["repos"][0]["exit_code"], 3);
+}
+
+#[test]
+fn sync_runs_the_pull_and_push_hooks() {
+    let sandbox = Sandbox::with_settings(
+        &["alpha"],
+        "hooks:\n  post_pull: echo \"post_pull $GITP_STATUS\" >> ../hooks.txt\n  pre_push: echo pre_push >> ../hooks.txt\n",
+    );
+    assert!(sandbox.gitp(&["clone", "--no-tui"]).status.success());
+
+    let output = sandbox.gitp(&["sync", "--yes", "--no-tui"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        fs::read_to_string(sandbox.path().join("ws").join("hooks.txt")).unwrap(),
+        "post_pull success\npre_push\n"
+    );
+}
+
+#[test]
+fn failing_pre_push_hook_stops_the_push() {
+    let sandbox = Sandbox::with_settings(&["alpha"], "hooks:\n  pre_push: exit 1\n");
+    assert!(sandbox.gitp(&["clone", "--no-tui"]).status.success());
+
+    let remote = sandbox.remote("alpha");
+    let remote_head = git(&remote, &["rev-parse", "main"]);
+    fs::write(sandbox.workspace("alpha").join("notes.txt"), "hello\n").unwrap();
+    let output = sandbox.gitp(&["push", "--yes", "--json"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        json_report(&output)["repos"][0]["message"],
+        "Failed: pre_push hook exited with 1"
+    );
+    assert_eq!(git(&remote, &["rev-parse", "main"]), remote_head);
+}
+
+#[test]
+fn before_all_and_after_all_run_once_per_command() {
+    let sandbox = Sandbox::with_settings(
+        &["alpha", "beta"],
+        "hooks:\n  before_all: touch before.txt\n  after_all: echo \"$GITP_COMMAND $GITP_SUCCEEDED\" > after.txt\n",
+    );
+
+    assert!(sandbox.gitp(&["clone", "--no-tui"]).status.success());
+    assert!(sandbox.path().join("before.txt").exists());
+    assert_eq!(
+        fs::read_to_string(sandbox.path().join("after.txt")).unwrap(),
+        "clone alpha beta\n"
+    );
+}
+
+#[test]
+fn failing_before_all_hook_runs_nothing() {
+    let sandbox =
+        Sandbox::with_settings(&["alpha"], "hooks:\n  before_all: echo offline; exit 1\n");
+
+    let output = sandbox.gitp(&["clone", "--no-tui"]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("offline"));
+    assert!(!sandbox.workspace("alpha").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn plugin_gets_the_repos_and_reports_progress() {
+    let sandbox = Sandbox::new(&["alpha", "beta"]);
+    sandbox.add_plugin(
+        "check",
+        "cat > \"$HOME/stdin.json\"\n\
+         echo \"args: $*\"\n\
+         echo '{\"repo\": \"alpha\", \"status\": \"success\", \"message\": \"Checked\"}'\n",
+    );
+
+    let output = sandbox.gitp(&["check", "--json", "--strict"]);
+    assert_eq!(output.status.code(), Some(0));
+    let report = json_report(&output);
+    assert_eq!(report["command"], "check");
+    assert_eq!(report["repos"][0]["message"], "Checked");
+    assert_eq!(report["repos"][1]["message"], "Done");
+    assert_eq!(report["repos"][1]["commands"][0], "gitp-check --strict");
+    assert_eq!(report["repos"][1]["output"], "args: --strict\n");
+
+    let stdin: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(sandbox.path().join("stdin.json")).unwrap())
+            .unwrap();
+    assert_eq!(stdin[0]["name"], "alpha");
+    assert_eq!(stdin[0]["branch"], "main");
+    assert_eq!(
+        Path::new(stdin[1]["path"].as_str().unwrap()),
+        sandbox.workspace("beta")
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn failed_plugin_fails_the_repos_it_did_not_finish() {
+    let sandbox = Sandbox::new(&["alpha", "beta"]);
+    sandbox.add_plugin(
+        "lint",
+        "echo '{\"repo\": \"alpha\", \"status\": \"success\"}'\nexit 4\n",
+    );
+
+    let output = sandbox.gitp(&["lint", "--json"]);
+    assert_eq!(output.status.code(), Some(1));
+    let report = json_report(&output);
+    assert_eq!(report["repos"][0]["status"], "success");
+    assert_eq!(
+        report["repos"][1]["message"],
+        "Failed: gitp-lint exited with 4"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn plugins_are_listed_in_the_help() {
+    let sandbox = Sandbox::new(&["alpha"]);
+    sandbox.add_plugin("check", "exit 0\n");
+
+    let output = sandbox.gitp(&["help"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("gitp-check"));
+}
+
+// The library API, without the binary: the group is absolute so the cwd does not matter
+#[test]
+fn runner_clones_and_reports_events() {
+    use gitp::{GitpSetting, Operation, RepoStatus, Repos, RunEvent, Runner};
+    use std::sync::{Arc, Mutex};
+
+    let sandbox = Sandbox::new(&["alpha"]);
+    let mut setting = GitpSetting::default();
+    setting.user.name = "tester".to_string();
+    setting.user.email = "tester@example.com".to_string();
+    setting.repos.push(Repos {
+        enabled: true,
+        remote: sandbox.remote("alpha").display().to_string(),
+        branch: "main".to_string(),
+        group: sandbox.path().join("ws").display().to_string(),
+        pull_strategy: None,
+        protected_branches: None,
+        hooks: None,
+    });
+
+    let statuses = Arc::new(Mutex::new(Vec::new()));
+    let events = Arc::clone(&statuses);
+    let runner = Runner::new(setting.clone(), Operation::Clone)
+        .unwrap()
+        .with_events(move |event| {
+            if let RunEvent::Status { status, .. } = event {
+                events.lock().unwrap().push(status);
+            }
+        });
+    let results = runner.run(&setting.enabled_repos());
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].name, "alpha");
+    assert_eq!(results[0].status, RepoStatus::Success);
+    assert!(results[0].logs[0].command.starts_with("git clone"));
+    assert_eq!(statuses.lock().unwrap().last(), Some(&RepoStatus::Success));
+    assert!(sandbox.workspace("alpha").join("README.md").exists());
+}
+
+#[cfg(not(feature = "builtin-git"))]
+#[test]
+fn builtin_backend_needs_the_feature() {
+    let sandbox = Sandbox::with_settings(&["alpha"], "backend: builtin\n");
+
+    let output = sandbox.gitp(&["clone", "--no-tui"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("builtin-git"));
+}
+
+#[cfg(feature = "builtin-git")]
+mod builtin {
+    use super::*;
+
+    fn sandbox(repos: &[&str]) -> Sandbox {
+        Sandbox::with_settings(repos, "backend: builtin\n")
+    }
+
+    #[test]
+    fn clone_runs_in_process() {
+        let sandbox = sandbox(&["alpha"]);
+
+        let output = sandbox.gitp(&["clone", "--json"]);
+        assert_eq!(output.status.code(), Some(0));
+        let report = json_report(&output);
+        assert_eq!(report["repos"][0]["status"], "success");
+        assert!(report["repos"][0]["commands"][0]
+            .as_str()
+            .unwrap()
+            .starts_with("gix clone"));
+        assert_eq!(
+            report["repos"][0]["commands"][1],
+            "gix config user.name tester"
+        );
+
+        let repo = sandbox.workspace("alpha");
+        assert_eq!(
+            fs::read_to_string(repo.join("README.md")).unwrap(),
+            "initial\n"
+        );
+        assert_eq!(
+            git(&repo, &["rev-parse", "--abbrev-ref", "HEAD"]).trim(),
+            "main"
+        );
+        assert_eq!(git(&repo, &["config", "user.name"]).trim(), "tester");
+    }
+
+    #[test]
+    fn clone_of_a_missing_remote_fails() {
+        let sandbox = sandbox(&["alpha"]);
+        fs::remove_dir_all(sandbox.remote("alpha")).unwrap();
+
+        let output = sandbox.gitp(&["clone", "--json"]);
+        assert_eq!(output.status.code(), Some(2));
+        assert_eq!(json_report(&output)["repos"][0]["status"], "failed");
+        assert!(!sandbox.workspace("alpha").exists());
+    }
+
+    #[test]
+    fn fetch_runs_in_process() {
+        let sandbox = sandbox(&["alpha"]);
+        assert!(sandbox.gitp(&["clone", "--no-tui"]).status.success());
+        sandbox.commit_to_remote("alpha", "NEWS.md", "news\n");
+
+        let output = sandbox.gitp(&["fetch", "--json"]);
+        assert_eq!(output.status.code(), Some(0));
+        let report = json_report(&output);
+        assert_eq!(report["repos"][0]["commands"][0], "gix fetch");
+        assert!(report["repos"][0]["output"]
+            .as_str()
+            .unwrap()
+            .contains("refs/remotes/origin/main: fast-forward"));
+        assert_eq!(
+            git(&sandbox.workspace("alpha"), &["rev-parse", "origin/main"]),
+            git(&sandbox.remote("alpha"), &["rev-parse", "main"])
+        );
+    }
+
+    #[test]
+    fn push_uses_in_process_status() {
+        let sandbox = sandbox(&["alpha"]);
+        assert!(sandbox.gitp(&["clone", "--no-tui"]).status.success());
+
+        let repo = sandbox.workspace("alpha");
+        fs::write(repo.join("README.md"), "changed\n").unwrap();
+        fs::write(repo.join("notes.txt"), "hello\n").unwrap();
+        let output = sandbox.gitp(&["push", "--yes", "--no-tui"]);
+        assert_eq!(output.status.code(), Some(0));
+
+        let remote = sandbox.remote("alpha");
+        assert_eq!(git(&remote, &["show", "main:README.md"]), "changed\n");
+        assert_eq!(git(&remote, &["show", "main:notes.txt"]), "hello\n");
+    }
+}